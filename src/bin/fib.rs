@@ -0,0 +1,404 @@
+use fib_circuit::{
+    circuits::circuit_naive::{FibCircuit, Visibility},
+    error::{hex, FibError},
+    keystore::KeyStore,
+    proof::{Curve, Layout, Proof},
+    prover::{prove, verify},
+    statement::DEFAULT_MAX_K,
+    vk::{export_vk, fingerprint, import_vk},
+    witness::ExactTrace,
+};
+use halo2_proofs::pasta::Fp;
+use std::{collections::HashMap, fs, path::Path, process::ExitCode};
+
+/*
+@note
+•   Command line front end for the naive FibCircuit, params are cached in a KeyStore.
+    Keys can't be written out, so every command generates the ones it needs.
+
+    fib setup   [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib prove   --x X --y Y --k K [--out FILE] [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib verify  <proof> [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib verify  <proof> --key FILE [--keys DIR]
+    fib inspect <proof>
+
+•   V is one of public-k, public-z (default), public-seeds, private.
+•   verify only trusts the configuration it is given: the same flags as prove (with the
+    same defaults) or the key file setup wrote. It rebuilds that key and rejects a proof
+    whose header or fingerprint names anything else, so a prover can't pick its own
+    max_k or visibility by writing them into the proof file.
+*/
+const USAGE: &str = "usage:
+    fib setup   [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib prove   --x X --y Y --k K [--out FILE] [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib verify  <proof> [--keys DIR] [--max-k M] [--domain-k D] [--visibility V]
+    fib verify  <proof> --key FILE [--keys DIR]
+    fib inspect <proof>";
+
+const DEFAULT_KEYS: &str = "keys";
+const DEFAULT_DOMAIN_K: u32 = 8;
+const DEFAULT_OUT: &str = "proof.bin";
+
+fn main() -> ExitCode{
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match Args::parse(&args) {
+        Ok(args) => run(args),
+        Err(e) => Err(CliError::Usage(e)),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(e)) => {
+            eprintln!("error: {}\n\n{}", e, USAGE);
+            ExitCode::from(2)
+        }
+        Err(CliError::Fib(e)) => {
+            eprintln!("error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[derive(Debug)]
+enum CliError{
+    //bad command line, print the usage too
+    Usage(String),
+    Fib(FibError),
+}
+
+impl From<FibError> for CliError{
+    fn from(e: FibError) -> Self{
+        CliError::Fib(e)
+    }
+}
+
+impl From<std::io::Error> for CliError{
+    fn from(e: std::io::Error) -> Self{
+        CliError::Fib(e.into())
+    }
+}
+
+//the subcommand, its positional arguments and its --flag value pairs
+struct Args{
+    command: String,
+    positional: Vec<String>,
+    flags: HashMap<String, String>,
+}
+
+impl Args{
+    fn parse(args: &[String]) -> Result<Self, String>{
+        let (command, rest) = args.split_first().ok_or("missing command")?;
+        let mut positional = vec![];
+        let mut flags = HashMap::new();
+        let mut rest = rest.iter();
+        while let Some(arg) = rest.next() {
+            match arg.strip_prefix("--") {
+                Some(flag) => {
+                    let value = rest.next().ok_or_else(|| format!("--{} needs a value", flag))?;
+                    if flags.insert(flag.to_string(), value.clone()).is_some() {
+                        return Err(format!("--{} given twice", flag));
+                    }
+                }
+                None => positional.push(arg.clone()),
+            }
+        }
+        Ok(Self{ command: command.clone(), positional, flags })
+    }
+
+    //checks every flag and positional argument given is one the command takes
+    fn expect(&self, flags: &[&str], positional: usize) -> Result<(), CliError>{
+        if let Some(flag) = self.flags.keys().find(|flag| !flags.contains(&flag.as_str())) {
+            return Err(CliError::Usage(format!("{} doesn't take --{}", self.command, flag)));
+        }
+        if self.positional.len() != positional {
+            return Err(CliError::Usage(format!(
+                "{} takes {} argument(s), got {}",
+                self.command,
+                positional,
+                self.positional.len()
+            )));
+        }
+        Ok(())
+    }
+
+    fn flag<T: std::str::FromStr>(&self, name: &str) -> Result<Option<T>, CliError>{
+        match self.flags.get(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| CliError::Usage(format!("invalid value for --{}: {}", name, value))),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T: std::str::FromStr>(&self, name: &str) -> Result<T, CliError>{
+        self.flag(name)?.ok_or_else(|| CliError::Usage(format!("missing --{}", name)))
+    }
+
+    fn visibility(&self) -> Result<Visibility, CliError>{
+        match self.flags.get("visibility").map(String::as_str) {
+            None | Some("public-z") => Ok(Visibility::PublicZ),
+            Some("public-k") => Ok(Visibility::PublicK),
+            Some("public-seeds") => Ok(Visibility::PublicSeeds),
+            Some("private") => Ok(Visibility::AllPrivateCommitted),
+            Some(other) => Err(CliError::Usage(format!("unknown visibility {}", other))),
+        }
+    }
+
+    //max_k, domain k and visibility, the same defaults for every command
+    fn config(&self) -> Result<(usize, u32, Visibility), CliError>{
+        let max_k = self.flag("max-k")?.unwrap_or(DEFAULT_MAX_K);
+        let domain_k = self.flag("domain-k")?.unwrap_or(DEFAULT_DOMAIN_K);
+        Ok((max_k, domain_k, self.visibility()?))
+    }
+
+    fn keys(&self) -> Result<KeyStore, CliError>{
+        let dir = self.flags.get("keys").map(String::as_str).unwrap_or(DEFAULT_KEYS);
+        Ok(KeyStore::new(dir)?)
+    }
+}
+
+fn run(args: Args) -> Result<(), CliError>{
+    match args.command.as_str() {
+        "setup" => setup(&args),
+        "prove" => prove_cmd(&args),
+        "verify" => verify_cmd(&args),
+        "inspect" => inspect(&args),
+        other => Err(CliError::Usage(format!("unknown command {}", other))),
+    }
+}
+
+fn setup(args: &Args) -> Result<(), CliError>{
+    args.expect(&["keys", "max-k", "domain-k", "visibility"], 0)?;
+    let store = args.keys()?;
+    let (max_k, domain_k, visibility) = args.config()?;
+
+    let params = store.params(domain_k)?;
+    let vk = store.verifying_key(&params, max_k, visibility)?;
+    println!("params  {}", store.params_path(domain_k).display());
+    println!("key     {}", store.export_key(&vk, max_k, visibility)?.display());
+    println!("vk      {}", hex(&fingerprint(&vk)));
+    Ok(())
+}
+
+fn prove_cmd(args: &Args) -> Result<(), CliError>{
+    args.expect(&["x", "y", "k", "out", "keys", "max-k", "domain-k", "visibility"], 0)?;
+    let x: u64 = args.required("x")?;
+    let y: u64 = args.required("y")?;
+    let k: usize = args.required("k")?;
+    let out = args.flags.get("out").map(String::as_str).unwrap_or(DEFAULT_OUT);
+    let store = args.keys()?;
+    let (max_k, domain_k, visibility) = args.config()?;
+
+    let circuit = FibCircuit::builder()
+        .seeds(Fp::from(x), Fp::from(y))
+        .index(k)
+        .max_k(max_k)
+        .visibility(visibility)
+        .build()?;
+    let params = store.params(domain_k)?;
+    let pk = store.proving_key(&params, max_k, visibility)?;
+
+    let instances = circuit.instances();
+    let z = circuit.z;
+    let envelope = Proof{
+        curve: Curve::PastaFp,
+        layout: Layout::Naive,
+        max_k: max_k as u64,
+        domain_k,
+        vk_fingerprint: Some(fingerprint(pk.get_vk())),
+        instances: vec![instances.clone()],
+        proof: prove(&params, &pk, circuit, &[&instances])?,
+    };
+    fs::write(out, envelope.to_bytes()?)?;
+    let _ = z.map(|z| println!("z       {:?}", z));
+    //z is only f(k) itself while the terms fit the field
+    let exact = ExactTrace::fib(x, y, k);
+    if let Some(n) = exact.wrapped::<Fp>() {
+        println!("note    f({}) and later exceed the field order, z is f({}) = {} mod p", n, k, exact.z());
+    }
+    println!("proof   {}", out);
+    Ok(())
+}
+
+fn verify_cmd(args: &Args) -> Result<(), CliError>{
+    args.expect(&["key", "keys", "max-k", "domain-k", "visibility"], 1)?;
+    let envelope = read_proof(&args.positional[0])?;
+    let store = args.keys()?;
+
+    //the key the verifier agreed to, nothing here comes from the proof file
+    let (params, vk, expected) = match args.flags.get("key") {
+        Some(path) => {
+            if let Some(flag) = ["max-k", "domain-k", "visibility"].into_iter().find(|flag| args.flags.contains_key(*flag)) {
+                return Err(CliError::Usage(format!("--key already fixes --{}", flag)));
+            }
+            let expected = store.read_key(Path::new(path))?;
+            let params = store.params(expected.domain_k)?;
+            let vk = import_vk(&params, &expected)?;
+            (params, vk, expected)
+        }
+        None => {
+            let (max_k, domain_k, visibility) = args.config()?;
+            let params = store.params(domain_k)?;
+            let vk = store.verifying_key(&params, max_k, visibility)?;
+            let expected = export_vk(&vk, max_k, visibility);
+            (params, vk, expected)
+        }
+    };
+    envelope.check_key(&expected)?;
+    verify(&params, &vk, &envelope.proof, &envelope.instance_slices())?;
+    println!("ok");
+    Ok(())
+}
+
+fn inspect(args: &Args) -> Result<(), CliError>{
+    args.expect(&[], 1)?;
+    let envelope = read_proof(&args.positional[0])?;
+    println!("curve        {:?}", envelope.curve);
+    println!("layout       {:?}", envelope.layout);
+    println!("max_k        {}", envelope.max_k);
+    println!("domain k     {}", envelope.domain_k);
+    match envelope.vk_fingerprint {
+        Some(fingerprint) => println!("vk           {}", hex(&fingerprint)),
+        None => println!("vk           (none, version 1)"),
+    }
+    if let Ok(visibility) = visibility_of(&envelope) {
+        println!("visibility   {:?}", visibility);
+    }
+    for (i, column) in envelope.instances.iter().enumerate() {
+        println!("instance {}", i);
+        for value in column {
+            println!("    {:?}", value);
+        }
+    }
+    println!("proof        {} bytes", envelope.proof.len());
+    Ok(())
+}
+
+fn read_proof(path: &str) -> Result<Proof, CliError>{
+    Ok(Proof::from_bytes(&fs::read(path)?)?)
+}
+
+//each visibility exposes a different number of values (see FibCircuit::instances)
+fn visibility_of(envelope: &Proof) -> Result<Visibility, FibError>{
+    match envelope.instances.as_slice() {
+        [column] => match column.len() {
+            1 => Ok(Visibility::AllPrivateCommitted),
+            2 => Ok(Visibility::PublicK),
+            3 => Ok(Visibility::PublicSeeds),
+            4 => Ok(Visibility::PublicZ),
+            n => Err(FibError::Serialization(format!("{} public inputs don't match any visibility", n))),
+        },
+        columns => Err(FibError::Serialization(format!("expected 1 instance column, found {}", columns.len()))),
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use fib_circuit::test_util::TempDir;
+
+    fn args(line: &str) -> Result<Args, String>{
+        Args::parse(&line.split_whitespace().map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_parse(){
+        let parsed = args("prove --x 1 --y 2 --k 9 extra").unwrap();
+        assert_eq!(parsed.command, "prove");
+        assert_eq!(parsed.positional, vec!["extra"]);
+        assert_eq!(parsed.flags.len(), 3);
+        assert_eq!(parsed.required::<u64>("y").unwrap(), 2);
+        assert_eq!(parsed.flag::<u64>("out").unwrap(), None);
+        assert!(matches!(parsed.required::<u64>("out"), Err(CliError::Usage(_))));
+        assert!(matches!(args("prove --x one").unwrap().flag::<u64>("x"), Err(CliError::Usage(_))));
+
+        assert_eq!(args("").err().unwrap(), "missing command");
+        assert_eq!(args("prove --x").err().unwrap(), "--x needs a value");
+        assert_eq!(args("prove --x 1 --x 2").err().unwrap(), "--x given twice");
+    }
+
+    #[test]
+    fn test_expect(){
+        assert!(args("verify proof.bin --keys dir").unwrap().expect(&["keys"], 1).is_ok());
+        let usage = |line: &str, flags: &[&str], positional| match args(line).unwrap().expect(flags, positional) {
+            Err(CliError::Usage(e)) => e,
+            other => panic!("expected a usage error, got {:?}", other),
+        };
+        assert_eq!(usage("inspect proof.bin --keys dir", &[], 1), "inspect doesn't take --keys");
+        assert_eq!(usage("inspect", &[], 1), "inspect takes 1 argument(s), got 0");
+        assert_eq!(usage("setup extra", &["keys"], 0), "setup takes 0 argument(s), got 1");
+    }
+
+    #[test]
+    fn test_visibility(){
+        assert_eq!(args("setup").unwrap().visibility().unwrap(), Visibility::PublicZ);
+        assert_eq!(args("setup --visibility private").unwrap().visibility().unwrap(), Visibility::AllPrivateCommitted);
+        assert!(matches!(args("setup --visibility all").unwrap().visibility(), Err(CliError::Usage(_))));
+
+        let envelope = |instances: Vec<Vec<Fp>>| Proof{
+            curve: Curve::PastaFp,
+            layout: Layout::Naive,
+            max_k: 16,
+            domain_k: 8,
+            vk_fingerprint: None,
+            instances,
+            proof: vec![],
+        };
+        for (n, visibility) in [
+            (1, Visibility::AllPrivateCommitted),
+            (2, Visibility::PublicK),
+            (3, Visibility::PublicSeeds),
+            (4, Visibility::PublicZ),
+        ] {
+            assert_eq!(visibility_of(&envelope(vec![vec![Fp::from(1); n]])).unwrap(), visibility);
+        }
+        assert!(matches!(visibility_of(&envelope(vec![vec![Fp::from(1); 5]])), Err(FibError::Serialization(_))));
+        assert!(matches!(visibility_of(&envelope(vec![])), Err(FibError::Serialization(_))));
+    }
+
+    /*
+    @note
+    •   setup, prove and verify through run, like the binary would. The proof only
+        verifies against the configuration it was made for, whatever its header says.
+    */
+    #[test]
+    fn test_round_trip(){
+        let dir = TempDir::new("round-trip");
+        let keys = dir.path("keys");
+        let run_line = |line: String| run(args(&line).unwrap());
+        let config = "--max-k 12 --domain-k 8 --visibility public-k";
+
+        run_line(format!("setup --keys {} {}", keys, config)).unwrap();
+        let key = KeyStore::new(&keys).unwrap().key_path(Layout::Naive, 12, 8, Visibility::PublicK);
+        let proof = dir.path("proof.bin");
+        run_line(format!("prove --x 1 --y 1 --k 9 --out {} --keys {} {}", proof, keys, config)).unwrap();
+
+        run_line(format!("verify {} --keys {} {}", proof, keys, config)).unwrap();
+        run_line(format!("verify {} --keys {} --key {}", proof, keys, key.display())).unwrap();
+        run_line(format!("inspect {}", proof)).unwrap();
+
+        //the default max_k (64) and visibility aren't the ones the proof was made for
+        assert!(matches!(
+            run_line(format!("verify {} --keys {}", proof, keys)),
+            Err(CliError::Fib(FibError::ConfigMismatch{ field: "max_k", expected: 64, found: 12 }))
+        ));
+        assert!(matches!(
+            run_line(format!("verify {} --keys {} --max-k 12", proof, keys)),
+            Err(CliError::Fib(FibError::KeyMismatch{ .. }))
+        ));
+
+        //a header rewritten to the verifier's max_k still names the prover's key
+        let mut envelope = read_proof(&proof).unwrap();
+        envelope.max_k = DEFAULT_MAX_K as u64;
+        fs::write(&proof, envelope.to_bytes().unwrap()).unwrap();
+        assert!(matches!(
+            run_line(format!("verify {} --keys {} --visibility public-k", proof, keys)),
+            Err(CliError::Fib(FibError::KeyMismatch{ .. }))
+        ));
+
+        assert!(matches!(
+            run_line(format!("verify {} --key {} --max-k 12", proof, key.display())),
+            Err(CliError::Usage(_))
+        ));
+    }
+}
@@ -0,0 +1,424 @@
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    poly::{Rotation},
+    plonk::{
+        Advice, ConstraintSystem, Circuit,
+        Column, Instance, Error,
+        Fixed, Selector, Expression,
+        VirtualCells,
+    },
+    pasta::group::ff::PrimeField,
+};
+use std::marker::PhantomData;
+
+use crate::error::FibError;
+use crate::witness::DoublingTrace;
+
+/*
+@note
+•   Same claim as circuit_naive (f(0) = x, f(1) = y, f(k) = z with x, y, z public),
+    but with O(log k) rows instead of k - 1.
+
+•   Let F be the standard sequence (F(0) = 0, F(1) = 1). In matrix form
+
+        [f(k+1)]   [1 1]^k [y]
+        [f(k)  ] = [1 0]   [x]    and   [1 1]^k = [F(k+1) F(k)  ]
+                                        [1 0]     [F(k)   F(k-1)]
+
+    so f(k) = x*F(k-1) + y*F(k) = x*(F(k+1) - F(k)) + y*F(k), i.e. it is enough to
+    compute the pair F(k), F(k+1).
+
+•   Fast doubling walks the bits of k from the most significant one. If (u, v) = (F(n), F(n+1)):
+
+        F(2n)   = u*(2v - u)
+        F(2n+1) = u^2 + v^2
+        F(2n+2) = F(2n) + F(2n+1)
+
+    so each bit maps (F(n), F(n+1)) to (F(2n), F(2n+1)) if it is 0 and to
+    (F(2n+1), F(2n+2)) if it is 1. The bits are private; acc rebuilds k from them
+    (acc_next = 2*acc + bit) so that (u, v) = (F(acc), F(acc+1)) in every row.
+
+•   The number of bits is fixed by the public bound max_k, so the shape does not
+    depend on k. The bits alone would allow any k < 2^bits, so the same rows also
+    rebuild a slack s from its own bits, and the output row checks k + s = max_k
+    against the fixed column. Both are below 2^bits, far from p, so that means
+    k <= max_k over the integers.
+    This is the table for max_k = 7 (3 bits), k = 5 = 0b101, s = 2 = 0b010:
+
+    u | v | bit | acc | slack bit | s | max_k | x | y | z
+    -----------------------------------------------------
+    0   1   1     0     0           0
+    1   1   0     1     1           0
+    1   2   1     2     0           1
+    5   8         5                 2   7       x   y   3x + 5y
+*/
+#[derive(Clone, Debug)]
+pub struct FibDoublingConfig{
+    //u = F(n), v = F(n+1)
+    advice: [Column<Advice>; 2],
+    //the bits of k and of the slack, and what they spell so far
+    bit: [Column<Advice>; 2],
+    acc: [Column<Advice>; 2],
+    max_k: Column<Fixed>,
+    //x, y, z on the output row
+    output: [Column<Advice>; 3],
+    instance: Column<Instance>,
+    s_init: Selector,
+    s_double: Selector,
+    s_output: Selector,
+}
+
+struct FibDoublingChip<F: PrimeField>{
+    config: FibDoublingConfig,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> FibDoublingChip<F>{
+    fn construct(cnfg: FibDoublingConfig) -> Self{
+        Self{
+            config: cnfg,
+            _marker: PhantomData
+        }
+    }
+
+    fn configure(
+        cs: &mut ConstraintSystem<F>,
+        advice: [Column<Advice>; 2],
+        bit: [Column<Advice>; 2],
+        acc: [Column<Advice>; 2],
+        max_k: Column<Fixed>,
+        output: [Column<Advice>; 3],
+        instance: Column<Instance>,
+    ) -> FibDoublingConfig {
+        let col_u: Column<Advice> = advice[0];
+        let col_v: Column<Advice> = advice[1];
+        let s_init: Selector = cs.selector();
+        let s_double: Selector = cs.selector();
+        let s_output: Selector = cs.selector();
+        for column in output {
+            cs.enable_equality(column);
+        }
+        cs.enable_equality(instance);
+
+        //(u, v, acc, slack) = (F(0), F(1), 0, 0) in the first row
+        cs.create_gate("init", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(s_init);
+            let u: Expression<F> = cs.query_advice(col_u, Rotation::cur());
+            let v: Expression<F> = cs.query_advice(col_v, Rotation::cur());
+            let n: Expression<F> = cs.query_advice(acc[0], Rotation::cur());
+            let slack: Expression<F> = cs.query_advice(acc[1], Rotation::cur());
+            let one: Expression<F> = Expression::Constant(F::ONE);
+
+            vec![
+                s.clone()*u,
+                s.clone()*(v - one),
+                s.clone()*n,
+                s*slack,
+            ]
+        });
+
+        cs.create_gate("double", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(s_double);
+            let u: Expression<F> = cs.query_advice(col_u, Rotation::cur());
+            let v: Expression<F> = cs.query_advice(col_v, Rotation::cur());
+            let [bit, slack_bit] = bit.map(|column| cs.query_advice(column, Rotation::cur()));
+            let [n, slack] = acc.map(|column| cs.query_advice(column, Rotation::cur()));
+            let u_next: Expression<F> = cs.query_advice(col_u, Rotation::next());
+            let v_next: Expression<F> = cs.query_advice(col_v, Rotation::next());
+            let [n_next, slack_next] = acc.map(|column| cs.query_advice(column, Rotation::next()));
+            let one: Expression<F> = Expression::Constant(F::ONE);
+            let two: Expression<F> = Expression::Constant(F::ONE.double());
+
+            //F(2n), F(2n+1)
+            let even: Expression<F> = u.clone()*(two.clone()*v.clone() - u.clone());
+            let odd: Expression<F> = u.clone()*u + v.clone()*v;
+
+            /*
+            @note
+            •   bit = 0: (u_next, v_next) = (F(2n), F(2n+1))
+            •   bit = 1: (u_next, v_next) = (F(2n+1), F(2n) + F(2n+1))
+            */
+            vec![
+                s.clone()*(even.clone() + bit.clone()*(odd.clone() - even.clone()) - u_next),
+                s.clone()*(odd + bit.clone()*even - v_next),
+                s.clone()*(two.clone()*n + bit.clone() - n_next),
+                s.clone()*bit.clone()*(one.clone() - bit),
+                s.clone()*(two*slack + slack_bit.clone() - slack_next),
+                s*slack_bit.clone()*(one - slack_bit),
+            ]
+        });
+
+        //f(k) = x*(F(k+1) - F(k)) + y*F(k), and k + s = max_k
+        cs.create_gate("output", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(s_output);
+            let u: Expression<F> = cs.query_advice(col_u, Rotation::cur());
+            let v: Expression<F> = cs.query_advice(col_v, Rotation::cur());
+            let x: Expression<F> = cs.query_advice(output[0], Rotation::cur());
+            let y: Expression<F> = cs.query_advice(output[1], Rotation::cur());
+            let z: Expression<F> = cs.query_advice(output[2], Rotation::cur());
+            let [n, slack] = acc.map(|column| cs.query_advice(column, Rotation::cur()));
+            let bound: Expression<F> = cs.query_fixed(max_k);
+
+            vec![
+                s.clone()*(x*(v - u.clone()) + y*u - z),
+                s*(n + slack - bound),
+            ]
+        });
+
+        FibDoublingConfig{
+            advice: [col_u, col_v],
+            bit,
+            acc,
+            max_k,
+            output,
+            instance,
+            s_init,
+            s_double,
+            s_output,
+        }
+    }
+
+    /*
+    @note
+    •   Lays out the trace, one row per bit plus the output row, and returns the x, y, z cells.
+    */
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
+    fn assign(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: Value<F>,
+        b: Value<F>,
+        trace: Value<&DoublingTrace<F>>,
+        z: Value<F>,
+        max_k: usize,
+        bits: usize,
+    ) -> Result<(AssignedCell<F, F>, AssignedCell<F, F>, AssignedCell<F, F>), Error> {
+        layouter.assign_region(
+            || "fib doubling",
+            |mut region| {
+                self.config.s_init.enable(&mut region, 0)?;
+                for row in 0..=bits {
+                    let [u, v, n, slack] = [0, 1, 2, 3].map(|i| trace.map(|trace| trace.row(row)[i]));
+                    region.assign_advice(|| "F(n)", self.config.advice[0], row, || u)?;
+                    region.assign_advice(|| "F(n+1)", self.config.advice[1], row, || v)?;
+                    region.assign_advice(|| "acc", self.config.acc[0], row, || n)?;
+                    region.assign_advice(|| "slack", self.config.acc[1], row, || slack)?;
+                    if row < bits {
+                        self.config.s_double.enable(&mut region, row)?;
+                        for (i, column) in self.config.bit.into_iter().enumerate() {
+                            region.assign_advice(|| "bit", column, row, || trace.map(|trace| trace.bit(row)[i]))?;
+                        }
+                    }
+                }
+
+                let row = bits;
+                self.config.s_output.enable(&mut region, row)?;
+                region.assign_fixed(|| "max_k", self.config.max_k, row, || Value::known(F::from(max_k as u64)))?;
+                let x_cell = region.assign_advice(|| "x", self.config.output[0], row, || a)?;
+                let y_cell = region.assign_advice(|| "y", self.config.output[1], row, || b)?;
+                let z_cell = region.assign_advice(|| "z", self.config.output[2], row, || z)?;
+                Ok((x_cell, y_cell, z_cell))
+            }
+        )
+    }
+}
+
+/*
+@note
+•   Takes the same inputs as FibCircuit, max_k is the same bound: it is fixed by the
+    keys and k <= max_k is checked in-circuit.
+*/
+#[derive(Default)]
+pub struct FibDoublingCircuit<F: PrimeField>{
+    pub a: Value<F>,
+    pub b: Value<F>,
+    pub k: Value<usize>,
+    pub z: Value<F>,
+    pub max_k: usize,
+}
+
+impl<F: PrimeField> FibDoublingCircuit<F>{
+    //number of bits (= doubling rows) needed for indices up to max_k
+    pub fn bits(&self) -> usize{
+        (usize::BITS - self.max_k.leading_zeros()).max(1) as usize
+    }
+
+    //k past max_k has no valid slack, the output row would reject it
+    pub fn check(&self) -> Result<(), FibError>{
+        let mut result = Ok(());
+        let _ = self.k.map(|k| if k > self.max_k {
+            result = Err(FibError::InvalidIndex{ k, max_k: self.max_k });
+        });
+        result
+    }
+
+    //public inputs matching the instance column: [f(0), f(1), z], empty if any of them is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        let _ = self.a.zip(self.b).zip(self.z).map(|((a, b), z)| instances.extend([a, b, z]));
+        instances
+    }
+}
+
+impl<F: PrimeField> Circuit<F> for FibDoublingCircuit<F>{
+    type Config = FibDoublingConfig;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            max_k: self.max_k,
+            ..Self::default()
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+        let col_u = cs.advice_column();
+        let col_v = cs.advice_column();
+        let bit = [cs.advice_column(), cs.advice_column()];
+        let acc = [cs.advice_column(), cs.advice_column()];
+        let max_k = cs.fixed_column();
+        let output = [cs.advice_column(), cs.advice_column(), cs.advice_column()];
+        let instance = cs.instance_column();
+        FibDoublingChip::configure(cs, [col_u, col_v], bit, acc, max_k, output, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+        let fib_chip: FibDoublingChip<F> = FibDoublingChip::construct(config.clone());
+        //synthesize can only report a bare halo2 error, check() has the details
+        self.check().map_err(|_| Error::Synthesis)?;
+        let trace = self.k.map(|k| DoublingTrace::new(k, self.max_k, self.bits()));
+        let (x_cell, y_cell, z_cell) = fib_chip.assign(
+            layouter.namespace(|| "fib doubling"),
+            self.a,
+            self.b,
+            trace.as_ref(),
+            self.z,
+            self.max_k,
+            self.bits(),
+        )?;
+
+        layouter.constrain_instance(x_cell.cell(), config.instance, 0)?;
+        layouter.constrain_instance(y_cell.cell(), config.instance, 1)?;
+        layouter.constrain_instance(z_cell.cell(), config.instance, 2)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::witness::fib;
+    use halo2_proofs::{circuit::SimpleFloorPlanner, dev::MockProver, pasta::{Fp, Fq}};
+
+    fn circuit<F: PrimeField>(a: F, b: F, k: usize, z: F, max_k: usize) -> FibDoublingCircuit<F>{
+        FibDoublingCircuit{
+            a: Value::known(a),
+            b: Value::known(b),
+            k: Value::known(k),
+            z: Value::known(z),
+            max_k,
+        }
+    }
+
+    fn verifies<F: PrimeField + Ord>(circ: FibDoublingCircuit<F>) -> bool{
+        MockProver::run(5, &circ, vec![circ.instances()]).unwrap().verify().is_ok()
+    }
+
+    fn complete<F: PrimeField + Ord>(){
+        let (a, b) = (F::from(1), F::from(2));
+        for k in [0, 1, 2, 5, 9, 16] {
+            assert!(verifies(circuit(a, b, k, fib(a, b, k), 16)));
+        }
+    }
+
+    #[test]
+    fn test_complete(){
+        complete::<Fp>();
+        complete::<Fq>();
+    }
+
+    #[test]
+    fn test_sound(){
+        assert!(!verifies(circuit(Fp::from(5), Fp::from(8), 11, Fp::from(55), 16)));
+        assert!(!verifies(circuit(Fq::from(5), Fq::from(8), 11, Fq::from(55), 16)));
+    }
+
+    //the doubling layout for k with the slack the prover picked, without check()
+    struct Unchecked{
+        circ: FibDoublingCircuit<Fp>,
+        slack: usize,
+    }
+
+    impl Circuit<Fp> for Unchecked{
+        type Config = FibDoublingConfig;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self{ circ: self.circ.without_witnesses(), slack: self.slack }
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+            FibDoublingCircuit::configure(cs)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+            let bits = self.circ.bits();
+            let trace = self.circ.k.map(|k| DoublingTrace::new(k, k + self.slack, bits));
+            let cells = FibDoublingChip::construct(config.clone()).assign(
+                layouter.namespace(|| "fib doubling"),
+                self.circ.a,
+                self.circ.b,
+                trace.as_ref(),
+                self.circ.z,
+                self.circ.max_k,
+                bits,
+            )?;
+            for (row, cell) in [cells.0, cells.1, cells.2].iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_check(){
+        let (a, b) = (Fp::from(1), Fp::from(2));
+        assert!(circuit(a, b, 16, fib(a, b, 16), 16).check().is_ok());
+        assert!(verifies(circuit(a, b, 16, fib(a, b, 16), 16)));
+
+        //17 still fits the 5 bits of max_k = 16, but not under it
+        let circ = circuit(a, b, 17, fib(a, b, 17), 16);
+        assert!(matches!(circ.check(), Err(FibError::InvalidIndex{ k: 17, max_k: 16 })));
+        assert!(matches!(MockProver::run(5, &circ, vec![circ.instances()]), Err(Error::Synthesis)));
+
+        //and without check() no slack brings k + s back down to max_k
+        for slack in 0..32 - 17 {
+            let instances = circ.instances();
+            let unchecked = Unchecked{ circ: circuit(a, b, 17, fib(a, b, 17), 16), slack };
+            assert!(MockProver::run(5, &unchecked, vec![instances]).unwrap().verify().is_err(), "slack {}", slack);
+        }
+        //while the honest slack is accepted
+        let unchecked = Unchecked{ circ: circuit(a, b, 9, fib(a, b, 9), 16), slack: 7 };
+        let instances = unchecked.circ.instances();
+        assert_eq!(MockProver::run(5, &unchecked, vec![instances]).unwrap().verify(), Ok(()));
+    }
+
+    /*
+    @note
+    •   2^20 would need a million rows in the naive layout, here it's 21.
+    */
+    #[test]
+    fn test_large_index(){
+        let (a, b) = (Fp::from(3), -Fp::from(7));
+        let k = 1 << 20;
+        let circ = circuit(a, b, k, fib(a, b, k), k);
+        assert_eq!(circ.bits(), 21);
+
+        let prover = MockProver::run(5, &circ, vec![circ.instances()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let wrong = circuit(a, b, k, fib(a, b, k - 1), k);
+        let prover = MockProver::run(5, &wrong, vec![wrong.instances()]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,228 @@
+use halo2_proofs::{
+    circuit::{Layouter, SimpleFloorPlanner, Value},
+    plonk::{Circuit, ConstraintSystem, Error},
+};
+
+use crate::circuits::circuit_naive::{FibChip, FibCircuit, FibCircuitConfig, Visibility};
+use crate::circuits::poseidon::{PoseidonChip, PoseidonField};
+use crate::error::FibError;
+
+/*
+@note
+•   One claim f(0) = x, f(1) = y, f(k) = z with the blinding r of its commitment,
+    i.e. a FibCircuit without the parts that are fixed by the keys.
+*/
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FibClaim<F: PoseidonField>{
+    pub a: Value<F>,
+    pub b: Value<F>,
+    pub k: Value<usize>,
+    pub z: Value<F>,
+    pub r: Value<F>,
+}
+
+impl<F: PoseidonField> From<FibCircuit<F>> for FibClaim<F>{
+    fn from(circuit: FibCircuit<F>) -> Self{
+        Self{
+            a: circuit.a,
+            b: circuit.b,
+            k: circuit.k,
+            z: circuit.z,
+            r: circuit.r,
+        }
+    }
+}
+
+/*
+@note
+•   Several claims in one proof. Each claim gets its own run of the fib chip (seed row
+    plus max_k rows) and its own commitment, one after the other in the same columns,
+    under the same selectors.
+•   The instance column is the concatenation of what each claim would expose on its
+    own (see FibCircuit::instances), in the order of the claims:
+
+    PublicZ     [x_0, y_0, z_0, H_0, x_1, y_1, z_1, H_1, ...]
+
+•   max_k, the visibility and the number of claims are fixed by the keys, every claim
+    can have its own (hidden) k.
+*/
+#[derive(Default)]
+pub struct MultiFibCircuit<F: PoseidonField>{
+    pub claims: Vec<FibClaim<F>>,
+    //largest index any of the claims can use
+    pub max_k: usize,
+    pub visibility: Visibility,
+}
+
+impl<F: PoseidonField> MultiFibCircuit<F>{
+    //the claim as a FibCircuit of its own
+    fn circuit(&self, claim: &FibClaim<F>) -> FibCircuit<F>{
+        FibCircuit{
+            a: claim.a,
+            b: claim.b,
+            k: claim.k,
+            z: claim.z,
+            r: claim.r,
+            max_k: self.max_k,
+            visibility: self.visibility,
+        }
+    }
+
+    //FibCircuit::check for every claim, and there has to be at least one
+    pub fn check(&self) -> Result<(), FibError>{
+        if self.claims.is_empty() {
+            return Err(FibError::MissingInput("claims"));
+        }
+        self.claims.iter().try_for_each(|claim| self.circuit(claim).check())
+    }
+
+    //public inputs of every claim in order, empty if any of them is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        for claim in &self.claims {
+            let claim = self.circuit(claim).instances();
+            if claim.is_empty() {
+                return vec![];
+            }
+            instances.extend(claim);
+        }
+        instances
+    }
+}
+
+impl<F: PoseidonField> Circuit<F> for MultiFibCircuit<F>{
+    type Config = FibCircuitConfig<F>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            claims: vec![FibClaim::default(); self.claims.len()],
+            max_k: self.max_k,
+            visibility: self.visibility,
+        }
+    }
+
+    //same columns and gates as a single claim
+    fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+        FibCircuit::configure(cs)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+        let fib_chip: FibChip<F> = FibChip::construct(config.fib, self.max_k);
+        let poseidon_chip: PoseidonChip<F> = PoseidonChip::construct(config.poseidon);
+        self.check().map_err(|_| Error::Synthesis)?;
+        let mut offset = 0;
+        for (i, claim) in self.claims.iter().enumerate() {
+            offset += self.circuit(claim).assign_claim(
+                &fib_chip,
+                &poseidon_chip,
+                layouter.namespace(|| format!("claim {}", i)),
+                config.instance,
+                offset,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use halo2_proofs::{
+        dev::MockProver,
+        pasta::{EqAffine, Fp, Fq},
+        plonk::keygen_vk,
+        poly::commitment::Params,
+    };
+
+    fn claim(x: u64, y: u64, k: usize, z: u64) -> FibClaim<Fp>{
+        FibClaim{
+            a: Value::known(Fp::from(x)),
+            b: Value::known(Fp::from(y)),
+            k: Value::known(k),
+            z: Value::known(Fp::from(z)),
+            r: Value::known(Fp::from(7)),
+        }
+    }
+
+    fn circ(claims: Vec<FibClaim<Fp>>, visibility: Visibility) -> MultiFibCircuit<Fp>{
+        MultiFibCircuit{
+            claims,
+            max_k: 16,
+            visibility,
+        }
+    }
+
+    #[test]
+    fn test_claims(){
+        let claims = vec![claim(1, 1, 12, 233), claim(1, 2, 0, 1), claim(2, 1, 5, 11)];
+        for visibility in [Visibility::PublicZ, Visibility::PublicK, Visibility::AllPrivateCommitted] {
+            let circ = circ(claims.clone(), visibility);
+            let single = circ.circuit(&claims[0]).instances();
+            let instances = circ.instances();
+            assert_eq!(instances.len(), 3 * single.len());
+            assert_eq!(instances[..single.len()], single);
+
+            let prover = MockProver::run(10, &circ, vec![instances]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+        }
+    }
+
+    //the same chips over Fq, each claim's z from the native fib
+    #[test]
+    fn test_fq(){
+        let claim = |k: usize, z: Fq| FibClaim{
+            a: Value::known(Fq::from(3)),
+            b: Value::known(-Fq::from(7)),
+            k: Value::known(k),
+            z: Value::known(z),
+            r: Value::known(Fq::from(7)),
+        };
+        let fib = |k| crate::witness::fib(Fq::from(3), -Fq::from(7), k);
+        for (z, ok) in [(fib(16), true), (fib(16) + Fq::from(1), false)] {
+            let circ = MultiFibCircuit{
+                claims: vec![claim(5, fib(5)), claim(16, z)],
+                max_k: 16,
+                visibility: Visibility::PublicZ,
+            };
+            let prover = MockProver::run(10, &circ, vec![circ.instances()]).unwrap();
+            assert_eq!(prover.verify().is_ok(), ok);
+        }
+    }
+
+    #[test]
+    fn test_one_bad_claim(){
+        //f(5) for 2, 1 is 11, every other claim is fine
+        let bad = circ(vec![claim(1, 1, 12, 233), claim(2, 1, 5, 12), claim(1, 2, 0, 1)], Visibility::PublicZ);
+        let prover = MockProver::run(10, &bad, vec![bad.instances()]).unwrap();
+        assert!(prover.verify().is_err());
+
+        //claims can't trade places in the instance column
+        let good = circ(vec![claim(1, 1, 12, 233), claim(2, 1, 5, 11)], Visibility::PublicZ);
+        let mut instances = good.instances();
+        instances.rotate_left(4);
+        let prover = MockProver::run(10, &good, vec![instances]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_check(){
+        assert!(matches!(circ(vec![], Visibility::PublicZ).check(), Err(FibError::MissingInput("claims"))));
+        let circ = circ(vec![claim(1, 1, 3, 3), claim(1, 1, 17, 2584)], Visibility::PublicZ);
+        assert!(matches!(circ.check(), Err(FibError::InvalidIndex{ k: 17, max_k: 16 })));
+        assert!(matches!(MockProver::run(10, &circ, vec![vec![]]), Err(Error::Synthesis)));
+    }
+
+    #[test]
+    fn test_shape(){
+        //the key depends on how many claims there are, not on their indices
+        let params: Params<EqAffine> = Params::new(10);
+        let pinned = |circ: &MultiFibCircuit<Fp>| format!("{:?}", keygen_vk(&params, circ).unwrap().pinned());
+        let two = circ(vec![claim(1, 1, 3, 3), claim(1, 1, 9, 55)], Visibility::PublicZ);
+        let other = circ(vec![claim(1, 1, 16, 1597), claim(1, 1, 0, 1)], Visibility::PublicZ);
+        let three = circ(vec![claim(1, 1, 3, 3); 3], Visibility::PublicZ);
+        assert_eq!(pinned(&two), pinned(&two.without_witnesses()));
+        assert_eq!(pinned(&two), pinned(&other));
+        assert_ne!(pinned(&two), pinned(&three));
+    }
+}
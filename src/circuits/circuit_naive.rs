@@ -1,15 +1,17 @@
 use halo2_proofs::{
     arithmetic::Field,
-    circuit::{AssignedCell, Cell, Layouter, SimpleFloorPlanner, Value},
-    poly::{Rotation},
+    circuit::{AssignedCell, Chip, Layouter, SimpleFloorPlanner, Value},
+    pasta::group::ff::PrimeField,
     plonk::{
-        Advice, ConstraintSystem, Circuit, 
-        Column, Fixed, Any, Error,
-        Selector, Expression,
-        VirtualCells,
+        ConstraintSystem, Circuit,
+        Column, Instance, Error,
     },
 };
-use std::marker::PhantomData;
+
+use crate::circuits::linear_recurrence::{LinearRecurrenceChip, LinearRecurrenceConfig, RecurrenceRow};
+use crate::circuits::poseidon::{self, PoseidonChip, PoseidonConfig, PoseidonField};
+use crate::error::FibError;
+use crate::witness::FibTrace;
 
 /*
 @note
@@ -20,7 +22,7 @@ use std::marker::PhantomData;
 •   Many tutorials for the Fibonacci circuit leave k and z public, but it's easy to 
     recover all the private values in this circuit by knowing these two numbers.
     Since this defeats the purpose of hiding the values in the circuit wires, 
-    it makes sense to keep z private (advice region) and k public (instance region) instead.
+    k is private by default, and what is public is a choice (see Visibility below).
 •   
 •   This is what the naive table for the fib circuit would look like
 •   if one were trying to prove that, given f(0) = 1 and f(1) = 1, 
@@ -43,165 +45,290 @@ use std::marker::PhantomData;
 
 /*
 @note
-•   We need a single chip for this circuit.
+•   If the number of rows depends on k, then the shape of the circuit (and therefore
+    the verifying key) gives k away, and keygen on a circuit without witnesses lays
+    out nothing at all.
+•   Instead we fix a public bound max_k at keygen time and always lay out max_k rows,
+    the row n producing f(n). Every row carries a hidden "active" bit (t). Active rows
+    compute c = a + b, inactive rows just pass b through (c = b), so once the sequence
+    reaches f(k) it stays frozen and the last c is f(k) for any k <= max_k.
+•   The first row starts from f(-1) = y - x and f(0) = x, which a seed row (x, y, y - x)
+    derives from the seeds. That way k = 0 (z = x) and k = 1 (z = y) need no special case.
+•   The index columns (i_in, i_out) count the active rows. An active row is only
+    allowed right after another active row (i_in must equal the fixed step column j),
+    so the active bits can't be switched back on after the sequence has frozen.
+•   This is what the table looks like for max_k = 7, f(0) = 1, f(1) = 1, k = 5.
+
+    a | b | c | t | i_in | i_out | j
+    ---------------------------------
+    1   1   0
+    0   1   1   1   0      1       0
+    1   1   2   1   1      2       1
+    1   2   3   1   2      3       2
+    2   3   5   1   3      4       3
+    3   5   8   1   4      5       4
+    5   8   8   0   5      5       5
+    8   8   8   0   5      5       6
+
+*/
+
+/*
+@note
+•   We need a single chip for this circuit: the linear recurrence chip with
+    coefficients [1, 1], i.e. f(n) = f(n-1) + f(n-2).
 •   Every chip has its own table apparently
 */
-#[derive(Clone, Debug)]
-pub struct FibConfig{
-    advice: [Column<Advice>; 3],
-    selector: Selector,
+pub type FibConfig<F> = LinearRecurrenceConfig<F, 2>;
+
+pub type FibChip<F> = LinearRecurrenceChip<F, 2>;
+
+pub type FibRow<F> = RecurrenceRow<F, 2>;
+
+//[c_1, c_2]
+pub fn coefficients<F: Field>() -> [F; 2]{
+    [F::ONE, F::ONE]
 }
 
 /*
 @note
-•   We have a PhantomData as a field of this struct
-    to influence the drop order of things (aka if this 
-    value needs to be dropped then other F's might need
-    to get dropped too)
+•   What a circuit embedding the fib chip can ask of it, on cells it already owns.
+•   load_seeds lays out the seed row and the first row (f(1)) with f(0), f(1) copied
+    from the given cells, step lays out the row after prev, and compute runs the whole
+    sequence up to max_k and returns the cell holding f(k), refusing a known k past
+    max_k like FibCircuit::check does. The values come from the trace (compute builds
+    it from the cells and k), k stays hidden in the active column either way.
 */
-struct FibChip<F: Field>{
-    config: FibConfig,
-    _marker: PhantomData<F>,
+pub trait FibInstructions<F: Field>: Chip<F>{
+    fn load_seeds(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<FibRow<F>, Error>;
+
+    fn step(
+        &self,
+        layouter: impl Layouter<F>,
+        prev: &FibRow<F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<FibRow<F>, Error>;
+
+    fn compute(
+        &self,
+        layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+        k: Value<usize>,
+    ) -> Result<AssignedCell<F, F>, Error>;
 }
 
-impl<F: Field> FibChip<F>{
-    //set chip config (what table it uses)
-    fn construct(cnfg: FibConfig) -> Self{
-        Self{
-            config: cnfg,
-            _marker: PhantomData 
-        }
+impl<F: PrimeField> FibInstructions<F> for FibChip<F>{
+    fn load_seeds(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<FibRow<F>, Error> {
+        let seeds = self.assign_seeds(layouter.namespace(|| "seeds"), trace)?;
+        layouter.assign_region(
+            || "copy seeds",
+            |mut region| {
+                region.constrain_equal(a.cell(), seeds.seeds[0].cell())?;
+                region.constrain_equal(b.cell(), seeds.seeds[1].cell())
+            }
+        )?;
+        self.assign_row(
+            layouter.namespace(|| "assign f_1"),
+            1,
+            trace.map(|t| t.inputs(1)).transpose_array(),
+            trace.map(|t| t.active(1)),
+            trace.map(|t| t.output(1)),
+            &seeds.window,
+            None,
+        )
     }
 
-    fn configure(
-        cs: &mut ConstraintSystem<F>,
-        advice: [Column<Advice>; 3],
-    ) -> FibConfig {
-        let col_a: Column<Advice> = advice[0];
-        let col_b: Column<Advice> = advice[1];
-        let col_c: Column<Advice> = advice[2];
-        let selector: Selector = cs.selector();
-        cs.enable_equality(col_a);
-        cs.enable_equality(col_b);
-        cs.enable_equality(col_c);
-        /*
-        @note
-        •   the closure here creates the gate that uses the input
-            from the advice columns to enforce the recursion constraints
+    fn step(
+        &self,
+        mut layouter: impl Layouter<F>,
+        prev: &FibRow<F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<FibRow<F>, Error> {
+        let n = prev.n + 1;
+        self.assign_row(
+            layouter.namespace(|| format!("assign f_{}", n)),
+            n,
+            trace.map(|t| t.inputs(n)).transpose_array(),
+            trace.map(|t| t.active(n)),
+            trace.map(|t| t.output(n)),
+            &prev.window(),
+            Some(prev),
+        )
+    }
 
-        •   Rotation::cur() and Rotation::next() control the positions relative to
-            the CURRENT REGION from which inputs/outputs to the constraints are chosen
-        
-        •   In every row of the advice region, we must have that a_i + b_i - c_i = 0
-        */
-        cs.create_gate("add", |cs: &mut VirtualCells<'_, F>| {           
-            //get expressions from values in table
-            let s: Expression<F> = cs.query_selector(selector);
+    fn compute(
+        &self,
+        mut layouter: impl Layouter<F>,
+        a: &AssignedCell<F, F>,
+        b: &AssignedCell<F, F>,
+        k: Value<usize>,
+    ) -> Result<AssignedCell<F, F>, Error> {
+        //max_k = 0 leaves no row to freeze f(k) in
+        if self.max_k() == 0 {
+            return Err(Error::Synthesis);
+        }
+        //a known k past the last row would leave every row active and return f(max_k)
+        let mut past_max_k = false;
+        let _ = k.map(|k| past_max_k = k > self.max_k());
+        if past_max_k {
+            return Err(Error::Synthesis);
+        }
+        let trace = a.value().zip(b.value()).zip(k).map(|((a, b), k)| FibTrace::fib(*a, *b, k));
+        let mut row = self.load_seeds(layouter.namespace(|| "seeds"), a, b, trace.as_ref())?;
+        while row.n < self.max_k() {
+            row = self.step(layouter.namespace(|| "step"), &row, trace.as_ref())?;
+        }
+        Ok(row.output)
+    }
+}
+
+/*
+@note
+•   The fib chip plus the Poseidon chip used for the commitment. The Poseidon chip
+    reuses the advice columns of the fib chip, its regions never overlap the rows.
+*/
+#[derive(Clone, Debug)]
+pub struct FibCircuitConfig<F: PoseidonField>{
+    pub(crate) fib: FibConfig<F>,
+    pub(crate) poseidon: PoseidonConfig<F>,
+    pub(crate) instance: Column<Instance>,
+}
 
-            //fib_n
-            let a: Expression<F> = cs.query_advice(col_a, Rotation::cur());
+/*
+@note
+•   Which parts of the claim f(0) = x, f(1) = y, f(k) = z end up in the instance column.
+    The commitment H(k, z, r) is always exposed, last, so whatever stays private is
+    still bound to the proof:
 
-            //fib_n+1
-            let b: Expression<F> = cs.query_advice(col_b, Rotation::cur());
+    PublicZ                 [x, y, z, H]
+    PublicSeeds             [x, y, H]
+    PublicK                 [k, H]
+    AllPrivateCommitted     [H]
 
-            //fib_n+2
-            let c: Expression<F> = cs.query_advice(col_c, Rotation::cur());
+•   The mode decides which cells get copied to the instance column, i.e. the
+    permutation, so keys generated for one mode don't verify proofs of another.
+*/
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Visibility{
+    PublicK,
+    #[default]
+    PublicZ,
+    PublicSeeds,
+    AllPrivateCommitted,
+}
 
-            //if selected, require that f_n + f_n+1 - f_n+2 = 0
-            vec![s*(a + b - c)]
-        });
-        FibConfig{
-            advice: [col_a, col_b, col_c],
-            selector: selector,
+impl Visibility{
+    //tag used when the mode is written out next to a key
+    pub fn id(self) -> u8{
+        match self {
+            Visibility::PublicK => 1,
+            Visibility::PublicZ => 2,
+            Visibility::PublicSeeds => 3,
+            Visibility::AllPrivateCommitted => 4,
         }
     }
 
-    /*
-    @todo
-    •   Should break this down
-    •   Assign first row of advice (x, y, z)
-    •   Assign first row of instance (just k)
-    */
-    fn assign_row(
-        &self, 
-        mut layouter: impl Layouter<F>, 
-        a: Value<F>, 
-        b: Value<F>, 
-        copy_cell: Option<AssignedCell<F, F>>,
-        z: Value<F>,
-        is_last: bool,
-    ) -> Result<(AssignedCell<F, F>, AssignedCell<F, F>, AssignedCell<F, F>), Error> {
-        //assign input a to region
-        layouter.assign_region(
-            || "first_row", //annotation
-            |mut region| { //assignment 
-                self.config.selector.enable(&mut region, 0)?;
-                /*
-                @note
-                •   Assign private values f_0, f_1, f_2 in the first advice row.
-                •   Note that the only public variable here is the index of the term
-                    to check. 
-                */
-                let a_cell = region.assign_advice(
-                    || "f_0", //annotation
-                    self.config.advice[0], //column
-                    0, //offset
-                    || a //closure which outputs the value to assign
-                )?;
-                
-                let b_cell = if let Some(cc) = &copy_cell {
-                    cc.copy_advice(
-                        || "current result = prev input", //annotation
-                        &mut region, //region,
-                        self.config.advice[1], //column
-                        0, //offset
-                    )?
-                } else {
-                    region.assign_advice(
-                        || "f_1",  //annotation
-                        self.config.advice[1], //column
-                        0, //offset
-                        || b //closure which outputs the value to assign
-                    )?
-                };
-
-                /*
-                @note
-                •   In the last row, we need to check that f(k) = f(k-1) + f(k-2) = z.
-                •   We can do this by putting z in the cell instead of a + b
-                */
-                let c_cell = region.assign_advice(
-                    || "f_2",  //annotation
-                    self.config.advice[2], //column
-                    0, //offset
-                    || if !is_last {
-                        a_cell.value().copied() + b_cell.value()
-                    } else{
-                        z
-                    }
-                )?;
-
-                Ok((a_cell, b_cell, c_cell))
-            }
-        )
+    pub fn from_id(id: u8) -> Result<Self, FibError>{
+        match id {
+            1 => Ok(Visibility::PublicK),
+            2 => Ok(Visibility::PublicZ),
+            3 => Ok(Visibility::PublicSeeds),
+            4 => Ok(Visibility::AllPrivateCommitted),
+            _ => Err(FibError::Serialization(format!("unknown visibility id {}", id))),
+        }
     }
 }
 
+/*
+@note
+•   max_k is public: it is fixed when the keys are generated and decides how many
+    rows get laid out. k itself only ever shows up in the (private) active column
+    (unless the visibility makes it public).
+•   Besides the seeds and z, the circuit exposes the commitment H(k, z, r) (Poseidon)
+    with a private blinding r. It binds the hidden k, so a claim can be committed
+    to now and opened later by revealing k and r.
+*/
 #[derive(Default)]
-struct FibCircuit<F: Field>{
+pub struct FibCircuit<F: PoseidonField>{
     //inputs to this circuit
     pub a: Value<F>,
     pub b: Value<F>,
     pub k: Value<usize>,
     pub z: Value<F>,
+    //blinding for the commitment
+    pub r: Value<F>,
+    //largest index this circuit can prove
+    pub max_k: usize,
+    //what the proof discloses, fixed when the keys are generated like max_k
+    pub visibility: Visibility,
 }
 
-impl<F: Field> Circuit<F> for FibCircuit<F>{
-    type Config = FibConfig;
+impl<F: PoseidonField> FibCircuit<F>{
+    /*
+    @note
+    •   Checks what the layout depends on before anything is assigned: there has to be
+        at least one row (max_k >= 1) and a known k can't be past the last row.
+    •   k = 0 and k = 1 are valid claims, z = f(0) = x and z = f(1) = y.
+    */
+    pub fn check(&self) -> Result<(), FibError>{
+        if self.max_k == 0 {
+            return Err(FibError::NotEnoughRows{ max_k: self.max_k });
+        }
+        let mut result = Ok(());
+        let _ = self.k.map(|k| if k > self.max_k {
+            result = Err(FibError::InvalidIndex{ k, max_k: self.max_k });
+        });
+        result
+    }
+
+    //public inputs matching the instance column (see Visibility), empty if any of them is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        let _ = self.a.zip(self.b).zip(self.k).zip(self.z).zip(self.r).map(|((((a, b), k), z), r)| {
+            let digest = commitment(k, z, r);
+            instances.extend(match self.visibility {
+                Visibility::PublicK => vec![F::from(k as u64), digest],
+                Visibility::PublicZ => vec![a, b, z, digest],
+                Visibility::PublicSeeds => vec![a, b, digest],
+                Visibility::AllPrivateCommitted => vec![digest],
+            })
+        });
+        instances
+    }
+
+    //the witness for the claim, unknown without a, b and k
+    pub fn trace(&self) -> Value<FibTrace<F>>{
+        self.a.zip(self.b).zip(self.k).map(|((a, b), k)| FibTrace::fib(a, b, k))
+    }
+}
+
+//H(k, z, r), the value the circuit exposes for a claim f(k) = z with blinding r
+pub fn commitment<F: PoseidonField>(k: usize, z: F, r: F) -> F{
+    poseidon::hash([F::from(k as u64), z, r])
+}
+
+impl<F: PoseidonField> Circuit<F> for FibCircuit<F>{
+    type Config = FibCircuitConfig<F>;
     type FloorPlanner = SimpleFloorPlanner;
     
     fn without_witnesses(&self) -> Self{
-        Self::default()
+        Self{
+            max_k: self.max_k,
+            visibility: self.visibility,
+            ..Self::default()
+        }
     }
 
     fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
@@ -209,45 +336,78 @@ impl<F: Field> Circuit<F> for FibCircuit<F>{
         let col_a = cs.advice_column();
         let col_b = cs.advice_column();
         let col_c = cs.advice_column();
-        FibChip::configure(cs, [col_a, col_b, col_c])
+        let active = cs.advice_column();
+        let index = [cs.advice_column(), cs.advice_column()];
+        let step = cs.fixed_column();
+        let constant = cs.fixed_column();
+        let instance = cs.instance_column();
+        //public inputs get copied in from the instance column
+        cs.enable_equality(instance);
+        FibCircuitConfig{
+            fib: FibChip::configure(cs, coefficients(), [col_a, col_b], col_c, active, index, step, constant),
+            poseidon: PoseidonChip::configure(cs, [col_a, col_b, col_c], index, constant),
+            instance,
+        }
     }
 
     fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
         //create chip
-        let fib_chip: FibChip<F> = FibChip::construct(config);
-        let mut fib0 = self.a;
-        let mut fib1 = self.b;
-        let mut fibtemp = self.b;
-        
-        //@note constrain first cell of instance col to equal k
-        
-        //layouter.constrain_instance(k_cell.cell(), self.config.instance, 0);
-        let mut copy_cell: Option<AssignedCell<F, F>> = None;
-        //@note since we get f(2) in the first row, we need k-1 iterations [0, v-1)
-        let result = self.k.map(|v| {
-            (0..v-1).for_each(|x| {
-                let (a_cell, b_cell, c_cell) = fib_chip.assign_row(
-                    layouter.namespace(||format!("assign f_{}, f_{}, f_{}", x, x+1, x+2)),
-                    fib0,
-                    fib1,
-                    copy_cell.clone(),
-                    self.z,
-                    x == v-2
-                ).unwrap();
-                copy_cell = Some(c_cell);
-                fibtemp = fib1;
-                fib1 = fib0 + &fibtemp;
-                fib0 = fibtemp;
-            });
-        });
+        let fib_chip: FibChip<F> = FibChip::construct(config.fib, self.max_k);
+        let poseidon_chip: PoseidonChip<F> = PoseidonChip::construct(config.poseidon);
+        //synthesize can only report a bare halo2 error, check() has the details
+        self.check().map_err(|_| Error::Synthesis)?;
+        self.assign_claim(&fib_chip, &poseidon_chip, layouter.namespace(|| "claim"), config.instance, 0)?;
         Ok(())
     }
 }
 
+impl<F: PoseidonField> FibCircuit<F>{
+    /*
+    @note
+    •   Lays out the claim and copies its public parts to the instance column starting
+        at the given row. Returns the number of instance rows used.
+    •   The seeds f(0), f(1) come from the seed row, f(k) from the last row (it has to
+        match the claimed z) and k from the last i_out. The visibility picks which of
+        them are public, followed by the commitment to k and z (see instances()).
+    */
+    pub(crate) fn assign_claim(
+        &self,
+        fib_chip: &FibChip<F>,
+        poseidon_chip: &PoseidonChip<F>,
+        mut layouter: impl Layouter<F>,
+        instance: Column<Instance>,
+        offset: usize,
+    ) -> Result<usize, Error>{
+        let (seeds, last) = fib_chip.assign(layouter.namespace(|| "fib"), self.trace().as_ref())?;
+        let r_cell = poseidon_chip.load_private(layouter.namespace(|| "blinding"), self.r)?;
+        let digest = poseidon_chip.hash(
+            layouter.namespace(|| "commitment"),
+            [last.index.clone(), last.output.clone(), r_cell],
+        )?;
+        let [x, y] = &seeds.seeds;
+        let public = match self.visibility {
+            Visibility::PublicK => vec![&last.index],
+            Visibility::PublicZ => vec![x, y, &last.output],
+            Visibility::PublicSeeds => vec![x, y],
+            Visibility::AllPrivateCommitted => vec![],
+        };
+        let rows = public.len() + 1;
+        for (row, cell) in public.into_iter().chain([&digest]).enumerate() {
+            layouter.constrain_instance(cell.cell(), instance, offset + row)?;
+        }
+        Ok(rows)
+    }
+}
+
 #[cfg(test)]
 mod tests{
     use super::*;
-    use halo2_proofs::{circuit::SimpleFloorPlanner, pasta::Fp, dev::MockProver};
+    use halo2_proofs::{
+        dev::{MockProver, VerifyFailure},
+        pasta::{EqAffine, Fp, Fq},
+        plonk::{keygen_vk, Advice},
+        poly::commitment::Params,
+    };
 
     #[test]
     fn test_complete(){
@@ -261,15 +421,18 @@ mod tests{
             b: Value::<Fp>::known(test_b),
             k: Value::<usize>::known(test_k),
             z: Value::<Fp>::known(test_z),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
         };
 
-        let prover = MockProver::run(8, &circ, vec![]).unwrap();
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
 
         assert_eq!(prover.verify(), Ok(()));
     }
 
+    //an unsatisfiable claim has to fail verification, not panic (see soundness.rs for exact failures)
     #[test]
-    #[should_panic]
     fn test_sound(){
         let test_a = Fp::from(5);
         let test_b = Fp::from(8);
@@ -281,10 +444,351 @@ mod tests{
             b: Value::<Fp>::known(test_b),
             k: Value::<usize>::known(test_k),
             z: Value::<Fp>::known(test_z),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
+        };
+
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    //the same claims over Fq, the scalar field on the other side of the Pasta cycle
+    #[test]
+    fn test_fq(){
+        let circ = |z: Fq| FibCircuit{
+            a: Value::known(Fq::from(1)),
+            b: Value::known(Fq::from(2)),
+            k: Value::known(9),
+            z: Value::known(z),
+            r: Value::known(Fq::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
         };
 
-        let prover = MockProver::run(8, &circ, vec![]).unwrap();
+        let good = circ(Fq::from(89));
+        let prover = MockProver::run(8, &good, vec![good.instances()]).unwrap();
         assert_eq!(prover.verify(), Ok(()));
+
+        let bad = circ(Fq::from(90));
+        let prover = MockProver::run(8, &bad, vec![bad.instances()]).unwrap();
+        assert!(prover.verify().is_err());
     }
-    
-}
\ No newline at end of file
+
+    #[test]
+    fn test_k_equals_max_k(){
+        let circ = FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(1)),
+            k: Value::known(12),
+            z: Value::known(Fp::from(233)),
+            r: Value::known(Fp::from(7)),
+            max_k: 12,
+            visibility: Visibility::PublicZ,
+        };
+
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    /*
+    @note
+    •   Same layout as FibCircuit, except that the row producing f(n) gets `value` in
+        its a cell instead of the previous b.
+    */
+    struct TamperedA{
+        circ: FibCircuit<Fp>,
+        n: usize,
+        value: Fp,
+    }
+
+    impl Circuit<Fp> for TamperedA{
+        type Config = FibCircuitConfig<Fp>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self{
+                circ: self.circ.without_witnesses(),
+                n: self.n,
+                value: self.value,
+            }
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+            FibCircuit::configure(cs)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+            let fib_chip: FibChip<Fp> = FibChip::construct(config.fib, self.circ.max_k);
+            let trace = self.circ.trace();
+            let seeds = fib_chip.assign_seeds(layouter.namespace(|| "seeds"), trace.as_ref())?;
+            let mut window = seeds.window;
+            let mut prev: Option<FibRow<Fp>> = None;
+            for n in 1..=self.circ.max_k {
+                let b = window[1].value().copied();
+                let a = if n == self.n { Value::known(self.value) } else { window[0].value().copied() };
+                //the rows from the forged one on add up, like honest rows would
+                let t = trace.as_ref().map(|t| t.active(n));
+                let row = fib_chip.assign_row(
+                    layouter.namespace(|| format!("row {}", n)),
+                    n,
+                    [a, b],
+                    t,
+                    t * (a + b) + (Value::known(Fp::ONE) - t) * b,
+                    &window,
+                    prev.as_ref(),
+                )?;
+                window = row.window();
+                prev = Some(row);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_unchained_a(){
+        /*
+        @note
+        •   Restart the sequence at f(6) with a = 4 instead of f(4) = 5: the row
+            itself is consistent (4 + 8 = 12) and so is everything after it
+            (up to the forged output 52), but the a cell no longer matches the previous b.
+        */
+        let circ = TamperedA{
+            circ: FibCircuit{
+                a: Value::known(Fp::from(1)),
+                b: Value::known(Fp::from(1)),
+                k: Value::known(9),
+                z: Value::known(Fp::from(52)),
+                r: Value::known(Fp::from(7)),
+                max_k: 9,
+                visibility: Visibility::PublicZ,
+            },
+            n: 6,
+            value: Fp::from(4),
+        };
+
+        let prover = MockProver::run(8, &circ, vec![vec![]]).unwrap();
+        let failures = prover.verify().unwrap_err();
+        assert!(!failures.is_empty());
+        assert!(failures.iter().all(|f| matches!(f, VerifyFailure::Permutation{ .. })));
+    }
+
+    /*
+    @note
+    •   A larger circuit that owns the seed cells (copied in from its instance column)
+        and uses the fib chip through FibInstructions for two hidden indices at once.
+    */
+    #[derive(Default)]
+    struct Embedded{
+        k: [Value<usize>; 2],
+        max_k: usize,
+    }
+
+    impl Circuit<Fp> for Embedded{
+        type Config = (FibConfig<Fp>, [Column<Advice>; 2], Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self{
+                max_k: self.max_k,
+                ..Self::default()
+            }
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let seeds = [cs.advice_column(), cs.advice_column()];
+            let output = cs.advice_column();
+            let active = cs.advice_column();
+            let index = [cs.advice_column(), cs.advice_column()];
+            let step = cs.fixed_column();
+            let constant = cs.fixed_column();
+            let instance = cs.instance_column();
+            cs.enable_equality(instance);
+            let fib = FibChip::configure(cs, coefficients(), seeds, output, active, index, step, constant);
+            (fib, seeds, instance)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+            let (fib, seeds, instance) = config;
+            let (a, b) = layouter.assign_region(
+                || "own cells",
+                |mut region| Ok((
+                    region.assign_advice_from_instance(|| "x", instance, 0, seeds[0], 0)?,
+                    region.assign_advice_from_instance(|| "y", instance, 1, seeds[1], 0)?,
+                ))
+            )?;
+            let fib_chip: FibChip<Fp> = FibChip::construct(fib, self.max_k);
+            for (i, k) in self.k.iter().enumerate() {
+                let z = fib_chip.compute(layouter.namespace(|| "compute"), &a, &b, *k)?;
+                layouter.constrain_instance(z.cell(), instance, 2 + i)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_instructions(){
+        let circ = Embedded{
+            k: [Value::known(5), Value::known(12)],
+            max_k: 12,
+        };
+        let instances = [1, 2, 13, 377].map(Fp::from).to_vec();
+        let prover = MockProver::run(8, &circ, vec![instances]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let instances = [1, 2, 13, 378].map(Fp::from).to_vec();
+        let prover = MockProver::run(8, &circ, vec![instances]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_instructions_past_max_k(){
+        //f(12) = 377 would verify if compute let k = 20 run off the last row
+        let circ = Embedded{
+            k: [Value::known(5), Value::known(20)],
+            max_k: 12,
+        };
+        let instances = [1, 2, 13, 377].map(Fp::from).to_vec();
+        assert!(matches!(MockProver::run(8, &circ, vec![instances]), Err(Error::Synthesis)));
+    }
+
+    #[test]
+    fn test_instance_mismatch(){
+        let circ = FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(2)),
+            k: Value::known(9),
+            z: Value::known(Fp::from(89)),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
+        };
+
+        //a correct witness still fails when the verifier expects a different output
+        let mut instances = circ.instances();
+        instances[2] = Fp::from(90);
+        instances[3] = commitment(9, Fp::from(90), Fp::from(7));
+        let prover = MockProver::run(8, &circ, vec![instances]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+
+    #[test]
+    fn test_small_k(){
+        let circ = |k: usize, z: u64| FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(2)),
+            k: Value::known(k),
+            z: Value::known(Fp::from(z)),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
+        };
+
+        //f(0) = x and f(1) = y, but not the other way around
+        for (k, z, ok) in [(0, 1, true), (1, 2, true), (0, 2, false), (1, 1, false), (2, 3, true)] {
+            let circ = circ(k, z);
+            let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+            assert_eq!(prover.verify().is_ok(), ok);
+        }
+    }
+
+    #[test]
+    fn test_check(){
+        let circ = |k: usize, max_k: usize| FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(1)),
+            k: Value::known(k),
+            z: Value::known(Fp::from(1)),
+            r: Value::known(Fp::from(7)),
+            max_k,
+            visibility: Visibility::PublicZ,
+        };
+
+        assert!(circ(16, 16).check().is_ok());
+        assert!(matches!(circ(17, 16).check(), Err(FibError::InvalidIndex{ k: 17, max_k: 16 })));
+        assert!(matches!(circ(0, 0).check(), Err(FibError::NotEnoughRows{ max_k: 0 })));
+
+        //synthesize refuses the claim instead of panicking
+        assert!(matches!(MockProver::run(8, &circ(17, 16), vec![vec![]]), Err(Error::Synthesis)));
+    }
+
+    /*
+    @note
+    •   Keys built from a circuit without witnesses have to match every k <= max_k,
+        so the verifying key must not change with k.
+    */
+    #[test]
+    fn test_shape_independent_of_k(){
+        let params: Params<EqAffine> = Params::new(8);
+        let circ = |k: usize, z: u64| FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(1)),
+            k: Value::known(k),
+            z: Value::known(Fp::from(z)),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility: Visibility::PublicZ,
+        };
+
+        let vk_empty = keygen_vk(&params, &circ(3, 2).without_witnesses()).unwrap();
+        let vk_3 = keygen_vk(&params, &circ(3, 2)).unwrap();
+        let vk_9 = keygen_vk(&params, &circ(9, 34)).unwrap();
+
+        let pinned = format!("{:?}", vk_empty.pinned());
+        assert_eq!(pinned, format!("{:?}", vk_3.pinned()));
+        assert_eq!(pinned, format!("{:?}", vk_9.pinned()));
+    }
+
+    const MODES: [Visibility; 4] = [
+        Visibility::PublicK,
+        Visibility::PublicZ,
+        Visibility::PublicSeeds,
+        Visibility::AllPrivateCommitted,
+    ];
+
+    #[test]
+    fn test_visibility(){
+        let circ = |z: u64, visibility: Visibility| FibCircuit{
+            a: Value::known(Fp::from(1)),
+            b: Value::known(Fp::from(2)),
+            k: Value::known(9),
+            z: Value::known(Fp::from(z)),
+            r: Value::known(Fp::from(7)),
+            max_k: 16,
+            visibility,
+        };
+
+        for visibility in MODES {
+            let good = circ(89, visibility);
+            let prover = MockProver::run(8, &good, vec![good.instances()]).unwrap();
+            assert_eq!(prover.verify(), Ok(()));
+
+            //a private z is still pinned down by the commitment
+            let bad = circ(90, visibility);
+            let prover = MockProver::run(8, &bad, vec![bad.instances()]).unwrap();
+            assert!(prover.verify().is_err());
+        }
+
+        assert_eq!(circ(89, Visibility::PublicK).instances()[0], Fp::from(9));
+        assert_eq!(circ(89, Visibility::AllPrivateCommitted).instances(), vec![commitment(9, Fp::from(89), Fp::from(7))]);
+    }
+
+    #[test]
+    fn test_visibility_in_vk(){
+        let params: Params<EqAffine> = Params::new(8);
+        let pinned: Vec<String> = MODES.iter().map(|visibility| {
+            let circ = FibCircuit::<Fp>{
+                max_k: 16,
+                visibility: *visibility,
+                ..FibCircuit::default()
+            };
+            format!("{:?}", keygen_vk(&params, &circ).unwrap().pinned())
+        }).collect();
+
+        for i in 0..MODES.len() {
+            for j in i + 1..MODES.len() {
+                assert_ne!(pinned[i], pinned[j]);
+            }
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Chip, Layouter, SimpleFloorPlanner, Value},
+    pasta::group::ff::PrimeField,
+    poly::Rotation,
+    plonk::{
+        Advice, ConstraintSystem, Circuit,
+        Column, Fixed, Instance, Error,
+        Selector, Expression,
+        VirtualCells,
+    },
+};
+
+use crate::circuits::linear_recurrence::{seed_gate, ActiveIndexChip, ActiveIndexConfig, SeedRows};
+use crate::error::FibError;
+use crate::witness::FibTrace;
+
+/*
+@note
+•   The naive fib layout spends a row (and a copy constraint) on every term. Packing
+    puts W advice columns in a row: the first two hold f(m-1), f(m) and the other W-2
+    are f(m+1)..f(m+W-2), each computed from the two cells before it by its own
+    polynomial of the "add" gate. The next row copies its first two cells from the
+    last two of this one, so max_k terms take ceil(max_k / (W-2)) rows.
+•   W = 3 is the naive chip. Wider rows trade columns for rows.
+•   k stays hidden the same way: every computed term has its own active bit t_j, an
+    active term is the sum of the two before it, an inactive one repeats the cell
+    before it. The bits, the count and the step are the ActiveIndexChip of the
+    recurrence chip with W-2 terms per row.
+•   The last row can go past max_k, so unlike the recurrence chip this one needs the
+    range check on its i_out to keep k <= max_k.
+•   A seed row (x, y, y - x) gives the first row f(-1), f(0), so k = 0 and k = 1
+    work like in circuit_naive.
+•   This is what the table looks like for W = 5, max_k = 7, f(0) = 1, f(1) = 1, k = 5.
+
+    x_0 | x_1 | x_2 | x_3 | x_4 | t_2 | t_3 | t_4 | i_in | i_out | j
+    -----------------------------------------------------------------
+    1     1     0
+    0     1     1     2     3     1     1     1     0      3       0
+    2     3     5     8     8     1     1     0     3      5       3
+    8     8     8     8     8     0     0     0     5      5       6
+
+*/
+#[derive(Clone, Debug)]
+pub struct PackedFibConfig<const W: usize>{
+    //f(m-1), f(m) and the W-2 terms computed from them
+    terms: [Column<Advice>; W],
+    //t_2..t_W-1, i_in, i_out and j
+    active_index: ActiveIndexConfig,
+    selector: Selector,
+    //enabled on the seed row
+    seed: Selector,
+}
+
+/*
+@note
+•   The cells of one packed row the next row (or the caller) needs to refer to.
+*/
+#[derive(Clone, Debug)]
+pub struct PackedRow<F: Field, const W: usize>{
+    //index of the term in the last cell
+    pub n: usize,
+    pub terms: [AssignedCell<F, F>; W],
+    pub index: AssignedCell<F, F>,
+}
+
+impl<F: Field, const W: usize> PackedRow<F, W>{
+    pub fn output(&self) -> &AssignedCell<F, F>{
+        &self.terms[W - 1]
+    }
+
+    //the cells the next row copies its first two cells from
+    pub fn window(&self) -> [AssignedCell<F, F>; 2]{
+        [self.terms[W - 2].clone(), self.terms[W - 1].clone()]
+    }
+}
+
+pub struct PackedFibChip<F: PrimeField, const W: usize>{
+    config: PackedFibConfig<W>,
+    active_index: ActiveIndexChip<F>,
+    max_k: usize,
+}
+
+impl<F: PrimeField, const W: usize> PackedFibChip<F, W>{
+    pub fn construct(cnfg: PackedFibConfig<W>, max_k: usize) -> Self{
+        Self{
+            active_index: ActiveIndexChip::construct(cnfg.active_index.clone(), max_k),
+            config: cnfg,
+            max_k,
+        }
+    }
+
+    pub fn max_k(&self) -> usize{
+        self.max_k
+    }
+
+    //rows needed for f(1)..f(max_k), not counting the seed row
+    pub fn rows(&self) -> usize{
+        self.max_k.div_ceil(W - 2)
+    }
+
+    /*
+    @note
+    •   active has to hold W-2 columns, one per computed term. Both are fixed by the
+        caller's constraint system, so a mismatch is a bug and panics.
+    */
+    pub fn configure(
+        cs: &mut ConstraintSystem<F>,
+        terms: [Column<Advice>; W],
+        active: Vec<Column<Advice>>,
+        index: [Column<Advice>; 2],
+        step: Column<Fixed>,
+        constant: Column<Fixed>,
+    ) -> PackedFibConfig<W> {
+        assert!(W >= 3, "a packed row needs at least 3 columns");
+        assert_eq!(active.len(), W - 2, "one active column per computed term");
+        let selector: Selector = cs.selector();
+        let seed: Selector = cs.selector();
+        for column in terms {
+            cs.enable_equality(column);
+        }
+
+        /*
+        @note
+        •   One polynomial per computed term x_j, j = 2..W-1:
+            x_j = x_j-2 + x_j-1 if t_j is set, x_j = x_j-1 otherwise.
+        */
+        let t_columns = active.clone();
+        cs.create_gate("add", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(selector);
+            let x: Vec<Expression<F>> = terms.iter().map(|column| cs.query_advice(*column, Rotation::cur())).collect();
+            let t: Vec<Expression<F>> = t_columns.iter().map(|column| cs.query_advice(*column, Rotation::cur())).collect();
+            let one: Expression<F> = Expression::Constant(F::ONE);
+
+            (2..W).map(|j| {
+                let t = t[j - 2].clone();
+                s.clone()*(t.clone()*(x[j - 2].clone() + x[j - 1].clone()) + (one.clone() - t)*x[j - 1].clone() - x[j].clone())
+            }).collect::<Vec<_>>()
+        });
+
+        //(f(0), f(1), f(-1)) with f(-1) + f(0) = f(1)
+        seed_gate(cs, seed, [F::ONE; 2], [terms[0], terms[1]], terms[2]);
+        let active_index = ActiveIndexChip::configure(cs, selector, active, index, step, constant, true);
+
+        PackedFibConfig{
+            terms,
+            active_index,
+            selector,
+            seed,
+        }
+    }
+
+    //the valid k table of the range check, once per circuit
+    pub fn load_table(&self, layouter: impl Layouter<F>) -> Result<(), Error> {
+        self.active_index.load_table(layouter)
+    }
+
+    //the seed row (f(0), f(1), f(-1)), the window of the first row is f(-1), f(0)
+    pub fn assign_seeds(
+        &self,
+        mut layouter: impl Layouter<F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<SeedRows<F, 2>, Error> {
+        layouter.assign_region(
+            || "seeds",
+            |mut region| {
+                self.config.seed.enable(&mut region, 0)?;
+                let x = region.assign_advice(|| "f_0", self.config.terms[0], 0, || trace.map(|t| t.term(0)))?;
+                let y = region.assign_advice(|| "f_1", self.config.terms[1], 0, || trace.map(|t| t.term(1)))?;
+                let prior = region.assign_advice(|| "f_-1", self.config.terms[2], 0, || trace.map(|t| t.term(-1)))?;
+                Ok(SeedRows{
+                    seeds: [x.clone(), y],
+                    window: [prior, x],
+                })
+            }
+        )
+    }
+
+    /*
+    @note
+    •   Assigns row number `row` (from 0), computing f(row·(W-2) + 1) onwards from the
+        window copied in from the previous row (or the seed row).
+    */
+    fn assign_row(
+        &self,
+        mut layouter: impl Layouter<F>,
+        row: usize,
+        window: &[AssignedCell<F, F>; 2],
+        prev: Option<&PackedRow<F, W>>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<PackedRow<F, W>, Error> {
+        layouter.assign_region(
+            || "row",
+            |mut region| {
+                self.config.selector.enable(&mut region, 0)?;
+
+                let mut terms = Vec::with_capacity(W);
+                for (i, cell) in window.iter().enumerate() {
+                    terms.push(cell.copy_advice(|| format!("x_{}", i), &mut region, self.config.terms[i], 0)?);
+                }
+
+                //terms before this row
+                let first = row*(W - 2);
+                let mut active = Vec::with_capacity(W - 2);
+                for j in 2..W {
+                    let n = first + j - 1;
+                    active.push(trace.map(|t| t.active(n)));
+                    terms.push(region.assign_advice(|| format!("f_{}", n), self.config.terms[j], 0, || trace.map(|t| t.output(n)))?);
+                }
+                let index = self.active_index.assign(&mut region, 0, first, &active, prev.map(|prev| &prev.index))?;
+
+                Ok(PackedRow{
+                    n: first + W - 2,
+                    terms: terms.try_into().map_err(|_| Error::Synthesis)?,
+                    index,
+                })
+            }
+        )
+    }
+
+    /*
+    @note
+    •   Lays out the seed row and the rows for f(1)..f(max_k) (possibly a few more) for
+        the hidden k of the trace, and returns the seed cells and the last row, whose
+        output is f(k). Like LinearRecurrenceChip::assign it rejects max_k = 0.
+    */
+    #[allow(clippy::type_complexity)]
+    pub fn assign(
+        &self,
+        mut layouter: impl Layouter<F>,
+        trace: Value<&FibTrace<F>>,
+    ) -> Result<(SeedRows<F, 2>, PackedRow<F, W>), Error> {
+        let seeds = self.assign_seeds(layouter.namespace(|| "seeds"), trace)?;
+        let mut window = seeds.window.clone();
+        let mut prev: Option<PackedRow<F, W>> = None;
+        for row in 0..self.rows() {
+            let next = self.assign_row(
+                layouter.namespace(|| format!("row {}", row)),
+                row,
+                &window,
+                prev.as_ref(),
+                trace,
+            )?;
+            window = next.window();
+            prev = Some(next);
+        }
+        Ok((seeds, prev.ok_or(Error::Synthesis)?))
+    }
+}
+
+impl<F: PrimeField, const W: usize> Chip<F> for PackedFibChip<F, W>{
+    type Config = PackedFibConfig<W>;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config{
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded{
+        &()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PackedFibCircuitConfig<const W: usize>{
+    fib: PackedFibConfig<W>,
+    instance: Column<Instance>,
+}
+
+/*
+@note
+•   The claim f(0) = x, f(1) = y, f(k) = z with a hidden k <= max_k on a packed layout.
+    The instance column is [x, y, z].
+*/
+#[derive(Default)]
+pub struct PackedFibCircuit<F: PrimeField, const W: usize>{
+    pub a: Value<F>,
+    pub b: Value<F>,
+    pub k: Value<usize>,
+    pub z: Value<F>,
+    //largest index this circuit can prove
+    pub max_k: usize,
+}
+
+impl<F: PrimeField, const W: usize> PackedFibCircuit<F, W>{
+    //same as FibCircuit::check: at least one row, and a known k can't be past max_k
+    pub fn check(&self) -> Result<(), FibError>{
+        if self.max_k == 0 {
+            return Err(FibError::NotEnoughRows{ max_k: self.max_k });
+        }
+        let mut result = Ok(());
+        let _ = self.k.map(|k| if k > self.max_k {
+            result = Err(FibError::InvalidIndex{ k, max_k: self.max_k });
+        });
+        result
+    }
+
+    //public inputs matching the instance column, empty if any of them is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        let _ = self.a.zip(self.b).zip(self.z).map(|((a, b), z)| instances.extend([a, b, z]));
+        instances
+    }
+}
+
+impl<F: PrimeField, const W: usize> Circuit<F> for PackedFibCircuit<F, W>{
+    type Config = PackedFibCircuitConfig<W>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            max_k: self.max_k,
+            ..Self::default()
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+        let terms = std::array::from_fn(|_| cs.advice_column());
+        let active = (2..W).map(|_| cs.advice_column()).collect();
+        let index = [cs.advice_column(), cs.advice_column()];
+        let step = cs.fixed_column();
+        let constant = cs.fixed_column();
+        let instance = cs.instance_column();
+        cs.enable_equality(instance);
+        PackedFibCircuitConfig{
+            fib: PackedFibChip::configure(cs, terms, active, index, step, constant),
+            instance,
+        }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+        let chip: PackedFibChip<F, W> = PackedFibChip::construct(config.fib, self.max_k);
+        //synthesize can only report a bare halo2 error, check() has the details
+        self.check().map_err(|_| Error::Synthesis)?;
+        chip.load_table(layouter.namespace(|| "valid k"))?;
+        let trace = self.a.zip(self.b).zip(self.k).map(|((a, b), k)| FibTrace::fib(a, b, k));
+        let (seeds, last) = chip.assign(layouter.namespace(|| "fib"), trace.as_ref())?;
+        let [x, y] = &seeds.seeds;
+        for (row, cell) in [x, y, last.output()].into_iter().enumerate() {
+            layouter.constrain_instance(cell.cell(), config.instance, row)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::circuits::circuit_naive::{coefficients, FibChip, FibConfig};
+    use crate::witness::fib;
+    use halo2_proofs::{dev::MockProver, pasta::{Fp, Fq}};
+
+    //the naive chip with the same instance column [x, y, z]
+    #[derive(Default)]
+    struct Naive{
+        a: Value<Fp>,
+        b: Value<Fp>,
+        k: Value<usize>,
+        max_k: usize,
+    }
+
+    impl Circuit<Fp> for Naive{
+        type Config = (FibConfig<Fp>, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self{
+                max_k: self.max_k,
+                ..Self::default()
+            }
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let inputs = [cs.advice_column(), cs.advice_column()];
+            let output = cs.advice_column();
+            let active = cs.advice_column();
+            let index = [cs.advice_column(), cs.advice_column()];
+            let step = cs.fixed_column();
+            let constant = cs.fixed_column();
+            let instance = cs.instance_column();
+            cs.enable_equality(instance);
+            let fib = FibChip::configure(cs, coefficients(), inputs, output, active, index, step, constant);
+            (fib, instance)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+            let (fib, instance) = config;
+            let chip: FibChip<Fp> = FibChip::construct(fib, self.max_k);
+            let trace = self.a.zip(self.b).zip(self.k).map(|((a, b), k)| FibTrace::fib(a, b, k));
+            let (seeds, last) = chip.assign(layouter.namespace(|| "fib"), trace.as_ref())?;
+            let [x, y] = &seeds.seeds;
+            for (row, cell) in [x, y, &last.output].into_iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    //the packed layout without check(), what a prover skipping it gets: the range check has to hold
+    struct Unchecked<F: PrimeField, const W: usize>(PackedFibCircuit<F, W>);
+
+    impl<F: PrimeField, const W: usize> Circuit<F> for Unchecked<F, W>{
+        type Config = PackedFibCircuitConfig<W>;
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self(self.0.without_witnesses())
+        }
+
+        fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+            PackedFibCircuit::<F, W>::configure(cs)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+            let circ = &self.0;
+            let chip: PackedFibChip<F, W> = PackedFibChip::construct(config.fib, circ.max_k);
+            chip.load_table(layouter.namespace(|| "valid k"))?;
+            let trace = circ.a.zip(circ.b).zip(circ.k).map(|((a, b), k)| FibTrace::fib(a, b, k));
+            let (seeds, last) = chip.assign(layouter.namespace(|| "fib"), trace.as_ref())?;
+            let [x, y] = &seeds.seeds;
+            for (row, cell) in [x, y, last.output()].into_iter().enumerate() {
+                layouter.constrain_instance(cell.cell(), config.instance, row)?;
+            }
+            Ok(())
+        }
+    }
+
+    const MAX_K: usize = 10;
+
+    fn packed<F: PrimeField, const W: usize>(x: F, y: F, k: usize, z: F) -> PackedFibCircuit<F, W>{
+        PackedFibCircuit{
+            a: Value::known(x),
+            b: Value::known(y),
+            k: Value::known(k),
+            z: Value::known(z),
+            max_k: MAX_K,
+        }
+    }
+
+    fn accepts<F: PrimeField + Ord, const W: usize>(x: F, y: F, k: usize, z: F) -> bool{
+        let circ = Unchecked(packed::<F, W>(x, y, k, z));
+        MockProver::run(6, &circ, vec![circ.0.instances()]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn test_same_as_naive(){
+        for (x, y) in [(1, 1), (2, 5)].map(|(x, y)| (Fp::from(x), Fp::from(y))) {
+            //k = max_k + 1 has no row (its z is rejected), z + 1 is just wrong
+            for k in 0..=MAX_K + 1 {
+                for z in [fib(x, y, k), fib(x, y, k) + Fp::ONE] {
+                    let naive = Naive{
+                        a: Value::known(x),
+                        b: Value::known(y),
+                        k: Value::known(k),
+                        max_k: MAX_K,
+                    };
+                    let expected = MockProver::run(6, &naive, vec![vec![x, y, z]]).unwrap().verify().is_ok();
+                    assert_eq!(expected, k <= MAX_K && z == fib(x, y, k));
+
+                    //W - 2 = 1, 2, 3 and 5 terms per row, 10 terms fill the last row or not
+                    assert_eq!(accepts::<Fp, 3>(x, y, k, z), expected, "W = 3, k = {}", k);
+                    assert_eq!(accepts::<Fp, 4>(x, y, k, z), expected, "W = 4, k = {}", k);
+                    assert_eq!(accepts::<Fp, 5>(x, y, k, z), expected, "W = 5, k = {}", k);
+                    assert_eq!(accepts::<Fp, 7>(x, y, k, z), expected, "W = 7, k = {}", k);
+                }
+            }
+        }
+    }
+
+    //the other side of the cycle, against the native fib
+    #[test]
+    fn test_fq(){
+        let (x, y) = (Fq::from(3), -Fq::from(7));
+        for k in 0..=MAX_K {
+            assert!(accepts::<Fq, 4>(x, y, k, fib(x, y, k)));
+            assert!(!accepts::<Fq, 4>(x, y, k, fib(x, y, k) + Fq::ONE));
+        }
+    }
+
+    #[test]
+    fn test_check(){
+        let (x, y) = (Fp::from(1), Fp::from(1));
+        let honest = packed::<Fp, 5>(x, y, MAX_K, fib(x, y, MAX_K));
+        assert!(honest.check().is_ok());
+        assert_eq!(MockProver::run(6, &honest, vec![honest.instances()]).unwrap().verify(), Ok(()));
+        let circ = |k| packed::<Fp, 5>(x, y, k, Fp::ZERO);
+        assert!(matches!(circ(MAX_K + 1).check(), Err(FibError::InvalidIndex{ k: 11, max_k: MAX_K })));
+        assert!(matches!(PackedFibCircuit{ max_k: 0, ..circ(0) }.check(), Err(FibError::NotEnoughRows{ max_k: 0 })));
+
+        //synthesize refuses the claim before building a trace for k
+        for k in [MAX_K + 1, usize::MAX] {
+            assert!(matches!(MockProver::run(6, &circ(k), vec![circ(k).instances()]), Err(Error::Synthesis)), "k = {}", k);
+        }
+    }
+
+    #[test]
+    fn test_rows(){
+        let config = PackedFibCircuit::<Fp, 5>::configure(&mut ConstraintSystem::default()).fib;
+        let chip = |max_k| PackedFibChip::<Fp, 5>::construct(config.clone(), max_k);
+        assert_eq!(chip(9).rows(), 3);
+        assert_eq!(chip(10).rows(), 4);
+        assert_eq!(chip(1).rows(), 1);
+    }
+}
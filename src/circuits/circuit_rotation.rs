@@ -0,0 +1,296 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    poly::{Rotation},
+    plonk::{
+        Advice, ConstraintSystem, Circuit,
+        Column, Instance, Error,
+        Selector, Expression,
+        VirtualCells,
+    },
+};
+use std::marker::PhantomData;
+
+use crate::error::FibError;
+use crate::witness::FibTrace;
+
+/*
+@note
+•   Same claim as circuit_naive: we know x, y, z, k such that f(0) = x, f(1) = y, f(k) = z,
+    with x, y, z public and k hidden below a public bound max_k.
+
+•   Instead of one region per row tied together with copy constraints, the whole
+    sequence lives in a single region and each row only holds the pair f(n), f(n+1).
+    The gate looks at the next row (Rotation::next()) to tie consecutive rows together,
+    so no copy constraints are needed between rows (only to the instance column).
+
+•   Every row has a hidden "active" bit t. Active rows step the pair forward
+    (a_next = b, b_next = a + b), inactive rows repeat it, and t can't be switched
+    back on once it is off. After max_k rows the a column holds f(k).
+•   This is what the table looks like for max_k = 5, f(0) = 1, f(1) = 1, k = 3.
+
+    a | b | t | s
+    -------------
+    1   1   1   1
+    1   2   1   1
+    2   3   1   1
+    3   5   0   1
+    3   5   0   1
+    3   5   0
+*/
+#[derive(Clone, Debug)]
+pub struct FibConfigV2{
+    advice: [Column<Advice>; 2],
+    active: Column<Advice>,
+    instance: Column<Instance>,
+    selector: Selector,
+}
+
+struct FibChipV2<F: Field>{
+    config: FibConfigV2,
+    _marker: PhantomData<F>,
+}
+
+impl<F: Field> FibChipV2<F>{
+    fn construct(cnfg: FibConfigV2) -> Self{
+        Self{
+            config: cnfg,
+            _marker: PhantomData
+        }
+    }
+
+    fn configure(
+        cs: &mut ConstraintSystem<F>,
+        advice: [Column<Advice>; 2],
+        active: Column<Advice>,
+        instance: Column<Instance>,
+    ) -> FibConfigV2 {
+        let col_a: Column<Advice> = advice[0];
+        let col_b: Column<Advice> = advice[1];
+        let selector: Selector = cs.selector();
+        //only needed to expose cells, rows are chained by the gate
+        cs.enable_equality(col_a);
+        cs.enable_equality(col_b);
+        cs.enable_equality(instance);
+
+        cs.create_gate("step", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(selector);
+
+            //f_n, f_n+1 in this row
+            let a: Expression<F> = cs.query_advice(col_a, Rotation::cur());
+            let b: Expression<F> = cs.query_advice(col_b, Rotation::cur());
+
+            //the pair in the next row
+            let a_next: Expression<F> = cs.query_advice(col_a, Rotation::next());
+            let b_next: Expression<F> = cs.query_advice(col_b, Rotation::next());
+
+            let t: Expression<F> = cs.query_advice(active, Rotation::cur());
+            let t_next: Expression<F> = cs.query_advice(active, Rotation::next());
+            let one: Expression<F> = Expression::Constant(F::ONE);
+
+            /*
+            @note
+            •   t = 1: a_next = b, b_next = a + b
+            •   t = 0: a_next = a, b_next = b
+            */
+            vec![
+                s.clone()*(a.clone() + t.clone()*(b.clone() - a.clone()) - a_next),
+                s.clone()*(b + t.clone()*a - b_next),
+                s.clone()*t.clone()*(one.clone() - t.clone()),
+                s*t_next*(one - t),
+            ]
+        });
+
+        FibConfigV2{
+            advice: [col_a, col_b],
+            active,
+            instance,
+            selector,
+        }
+    }
+
+    /*
+    @note
+    •   Lays out max_k steps plus the final row in one region.
+    •   Returns the cells holding f(0), f(1) and f(k) (which is assigned z, so
+        the gate in the last step checks it).
+    */
+    #[allow(clippy::type_complexity)]
+    fn assign(
+        &self,
+        mut layouter: impl Layouter<F>,
+        trace: Value<&FibTrace<F>>,
+        z: Value<F>,
+        max_k: usize,
+    ) -> Result<(AssignedCell<F, F>, AssignedCell<F, F>, AssignedCell<F, F>), Error> {
+        layouter.assign_region(
+            || "fib",
+            |mut region| {
+                //row r holds the pair frozen at f(k), f(k+1) and steps it while r < k
+                let pair = |row: usize| trace.map(|t| {
+                    let n = row.min(t.k()) as isize;
+                    (t.term(n), t.term(n + 1))
+                }).unzip();
+                let mut seeds = None;
+                for row in 0..max_k {
+                    self.config.selector.enable(&mut region, row)?;
+                    let (fib0, fib1) = pair(row);
+                    let a_cell = region.assign_advice(|| "f_n", self.config.advice[0], row, || fib0)?;
+                    let b_cell = region.assign_advice(|| "f_n+1", self.config.advice[1], row, || fib1)?;
+                    region.assign_advice(|| "active", self.config.active, row, || trace.map(|t| t.active(row + 1)))?;
+                    if row == 0 {
+                        seeds = Some((a_cell, b_cell));
+                    }
+                }
+
+                //final row: no step of its own, a holds f(k)
+                let z_cell = region.assign_advice(|| "f_k", self.config.advice[0], max_k, || z)?;
+                let b_cell = region.assign_advice(|| "f_k+1", self.config.advice[1], max_k, || pair(max_k).1)?;
+                region.assign_advice(|| "active", self.config.active, max_k, || Value::known(F::ZERO))?;
+
+                let (a_cell, b_cell) = seeds.unwrap_or((z_cell.clone(), b_cell));
+                Ok((a_cell, b_cell, z_cell))
+            }
+        )
+    }
+}
+
+/*
+@note
+•   Takes the same inputs as FibCircuit, so the two layouts can be compared directly.
+*/
+#[derive(Default)]
+pub struct FibCircuitV2<F: Field>{
+    pub a: Value<F>,
+    pub b: Value<F>,
+    pub k: Value<usize>,
+    pub z: Value<F>,
+    pub max_k: usize,
+}
+
+impl<F: Field> FibCircuitV2<F>{
+    //a known k can't be past max_k, there are only max_k steps to freeze it at
+    pub fn check(&self) -> Result<(), FibError>{
+        let mut result = Ok(());
+        let _ = self.k.map(|k| if k > self.max_k {
+            result = Err(FibError::InvalidIndex{ k, max_k: self.max_k });
+        });
+        result
+    }
+
+    //public inputs matching the instance column: [f(0), f(1), z], empty if any of them is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        let _ = self.a.zip(self.b).zip(self.z).map(|((a, b), z)| instances.extend([a, b, z]));
+        instances
+    }
+}
+
+impl<F: Field> Circuit<F> for FibCircuitV2<F>{
+    type Config = FibConfigV2;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            max_k: self.max_k,
+            ..Self::default()
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+        let col_a = cs.advice_column();
+        let col_b = cs.advice_column();
+        let active = cs.advice_column();
+        let instance = cs.instance_column();
+        FibChipV2::configure(cs, [col_a, col_b], active, instance)
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+        let fib_chip: FibChipV2<F> = FibChipV2::construct(config.clone());
+        //synthesize can only report a bare halo2 error, check() has the details
+        self.check().map_err(|_| Error::Synthesis)?;
+        let trace = self.a.zip(self.b).zip(self.k).map(|((a, b), k)| FibTrace::fib(a, b, k));
+        let (a_cell, b_cell, z_cell) = fib_chip.assign(
+            layouter.namespace(|| "fib"),
+            trace.as_ref(),
+            self.z,
+            self.max_k,
+        )?;
+
+        layouter.constrain_instance(a_cell.cell(), config.instance, 0)?;
+        layouter.constrain_instance(b_cell.cell(), config.instance, 1)?;
+        layouter.constrain_instance(z_cell.cell(), config.instance, 2)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::circuits::circuit_naive::{FibCircuit, Visibility};
+    use halo2_proofs::{dev::MockProver, pasta::{group::ff::PrimeField, Fp, Fq}};
+
+    fn circuit<F: PrimeField>(a: u64, b: u64, k: usize, z: u64, max_k: usize) -> FibCircuitV2<F>{
+        FibCircuitV2{
+            a: Value::known(F::from(a)),
+            b: Value::known(F::from(b)),
+            k: Value::known(k),
+            z: Value::known(F::from(z)),
+            max_k,
+        }
+    }
+
+    fn verifies<F: PrimeField + Ord>(circ: FibCircuitV2<F>) -> bool{
+        MockProver::run(8, &circ, vec![circ.instances()]).unwrap().verify().is_ok()
+    }
+
+    #[test]
+    fn test_complete(){
+        for (k, z) in [(0, 1), (1, 2), (2, 3), (9, 89), (16, 2584)] {
+            assert!(verifies(circuit::<Fp>(1, 2, k, z, 16)));
+            assert!(verifies(circuit::<Fq>(1, 2, k, z, 16)));
+        }
+    }
+
+    #[test]
+    fn test_sound(){
+        assert!(!verifies(circuit::<Fp>(5, 8, 11, 55, 16)));
+        assert!(!verifies(circuit::<Fq>(5, 8, 11, 55, 16)));
+    }
+
+    #[test]
+    fn test_check(){
+        assert!(circuit::<Fp>(1, 2, 16, 2584, 16).check().is_ok());
+        assert!(matches!(circuit::<Fp>(1, 2, 17, 4181, 16).check(), Err(FibError::InvalidIndex{ k: 17, max_k: 16 })));
+
+        //synthesize refuses the claim before building a trace for k
+        for k in [17, usize::MAX] {
+            let circ = circuit::<Fp>(1, 2, k, 0, 16);
+            assert!(matches!(MockProver::run(8, &circ, vec![circ.instances()]), Err(Error::Synthesis)), "k = {}", k);
+        }
+    }
+
+    /*
+    @note
+    •   Both layouts have to agree on which claims are true.
+    */
+    #[test]
+    fn test_matches_naive(){
+        for (k, z, ok) in [(2, 3, true), (7, 34, true), (7, 35, false), (12, 377, true)] {
+            let v2 = circuit(1, 2, k, z, 12);
+            let naive = FibCircuit{
+                a: v2.a,
+                b: v2.b,
+                k: v2.k,
+                z: v2.z,
+                r: Value::known(Fp::from(7)),
+                max_k: 12,
+                visibility: Visibility::PublicZ,
+            };
+            let v2_ok = MockProver::run(8, &v2, vec![v2.instances()]).unwrap().verify().is_ok();
+            let naive_ok = MockProver::run(8, &naive, vec![naive.instances()]).unwrap().verify().is_ok();
+            assert_eq!(v2_ok, ok);
+            assert_eq!(naive_ok, ok);
+        }
+    }
+}
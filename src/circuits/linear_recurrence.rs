@@ -0,0 +1,770 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Chip, Layouter, Region, SimpleFloorPlanner, Value},
+    pasta::group::ff::PrimeField,
+    poly::{Rotation},
+    plonk::{
+        Advice, ConstraintSystem, Circuit,
+        Column, Fixed, Instance, Error,
+        Selector, Expression, TableColumn,
+        VirtualCells,
+    },
+};
+use std::marker::PhantomData;
+
+use crate::error::FibError;
+use crate::witness::Trace;
+
+/*
+@note
+•   The naive fib layout generalised to any recurrence of order N with constant
+    coefficients: f(n) = c_1·f(n-1) + c_2·f(n-2) + ... + c_N·f(n-N), c_N != 0.
+•   Each row holds the N previous terms x_0..x_N-1 = f(n-N)..f(n-1) and the output y.
+    Active rows compute y = Σ c_i·x_N-i, inactive rows pass the last term through
+    (y = x_N-1), so the sequence freezes at f(k) for any hidden k <= max_k.
+•   Rows are chained with copy constraints: x_i = previous x_i+1 and x_N-1 = previous y.
+•   For the output to be f(k) for every k >= 0 (including the seeds themselves), the
+    first row has to start from the window f(-N+1)..f(0). The seed rows run the
+    recurrence backwards to get there: each one holds a window f(m)..f(m+N-1) and
+    its output is f(m-1), with c_N·y + Σ_i<N c_i·x_N-1-i = x_N-1.
+•   After the N-1 seed rows come max_k rows producing f(1)..f(max_k), the index counts
+    the active ones from 0.
+•   The active bits and the running index (i_in, i_out, fixed step j) work exactly
+    like in circuit_naive: t is a bit, i_out = i_in + t, and t = 1 only if i_in = j.
+    They are laid out by the ActiveIndexChip below, with one term per row.
+•   That chain is already a range check on k: i_in starts at the constant 0 and each
+    of the max_k rows adds a bit, so the last i_out is in 0..=max_k whatever the
+    prover does. A lookup into a table of valid indices could never fail here, so
+    there is none (the packed layout does need one, see circuit_packed).
+•   Fibonacci is the [1, 1] instance. This is what the table looks like for
+    Tribonacci ([1, 1, 1]), max_k = 5, f(0..3) = 0, 0, 1, k = 4 (the first
+    two rows are seed rows).
+
+    x_0 | x_1 | x_2 | y  | t | i_in | i_out | j
+    --------------------------------------------
+    0     0     1     1
+    1     0     0     -1
+    -1    1     0     0    1   0      1       0
+    1     0     0     1    1   1      2       1
+    0     0     1     1    1   2      3       2
+    0     1     1     2    1   3      4       3
+    1     1     2     2    0   4      4       4
+
+*/
+#[derive(Clone, Debug)]
+pub struct LinearRecurrenceConfig<F: Field, const N: usize>{
+    //[c_1..c_N]
+    pub(crate) coefficients: [F; N],
+    pub(crate) inputs: [Column<Advice>; N],
+    pub(crate) output: Column<Advice>,
+    pub(crate) active_index: ActiveIndexConfig,
+    pub(crate) selector: Selector,
+    //enabled on the seed rows
+    pub(crate) seed: Selector,
+}
+
+/*
+@note
+•   The cells of one row of the table that later rows (or the caller) need to refer to.
+*/
+#[derive(Clone, Debug)]
+pub struct RecurrenceRow<F: Field, const N: usize>{
+    //index of the term in the output cell
+    pub n: usize,
+    pub inputs: [AssignedCell<F, F>; N],
+    pub output: AssignedCell<F, F>,
+    pub index: AssignedCell<F, F>,
+}
+
+impl<F: Field, const N: usize> RecurrenceRow<F, N>{
+    //the cells the next row copies its inputs from
+    pub fn window(&self) -> [AssignedCell<F, F>; N]{
+        std::array::from_fn(|i| if i + 1 < N { self.inputs[i + 1].clone() } else { self.output.clone() })
+    }
+}
+
+/*
+@note
+•   The seed cells f(0)..f(N-1) and the window f(-N+1)..f(0) the first row starts from.
+*/
+#[derive(Clone, Debug)]
+pub struct SeedRows<F: Field, const N: usize>{
+    pub seeds: [AssignedCell<F, F>; N],
+    pub window: [AssignedCell<F, F>; N],
+}
+
+/*
+@note
+•   The part of a row that hides k, shared by the recurrence chip and the packed chip.
+    A row computing m terms has m active bits t_1..t_m, the running count i_in, i_out
+    of active terms and the fixed step j, the number of terms before the row.
+•   Every t_i is a bit and can only be set if t_i-1 is, i_out = i_in + Σ t_i, and t_1
+    can only be set if every earlier term was active (i_in = j), so the bits can't be
+    switched back on after the sequence has frozen.
+•   The first row's i_in is pinned to the constant 0, every other one is copied from
+    the previous row's i_out.
+•   With a range check the i_out of the row holding f(max_k) is also looked up in a
+    table of 0..=max_k. That is only needed when the last row can compute terms past
+    f(max_k), with one term per row the count can't get past max_k anyway.
+*/
+#[derive(Clone, Debug)]
+pub struct ActiveIndexConfig{
+    //t_1..t_m, one per term computed in a row
+    pub(crate) active: Vec<Column<Advice>>,
+    pub(crate) index: [Column<Advice>; 2],
+    pub(crate) step: Column<Fixed>,
+    //valid values of k, 0..=max_k, and the selector on the row where i_out = k
+    pub(crate) range: Option<(TableColumn, Selector)>,
+}
+
+pub struct ActiveIndexChip<F: PrimeField>{
+    config: ActiveIndexConfig,
+    max_k: usize,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PrimeField> ActiveIndexChip<F>{
+    pub fn construct(cnfg: ActiveIndexConfig, max_k: usize) -> Self{
+        Self{
+            config: cnfg,
+            max_k,
+            _marker: PhantomData
+        }
+    }
+
+    //the "active" gate is enabled by the selector of the rows it belongs to
+    pub fn configure(
+        cs: &mut ConstraintSystem<F>,
+        selector: Selector,
+        active: Vec<Column<Advice>>,
+        index: [Column<Advice>; 2],
+        step: Column<Fixed>,
+        constant: Column<Fixed>,
+        range: bool,
+    ) -> ActiveIndexConfig {
+        assert!(!active.is_empty(), "a row computes at least one term");
+        for column in index {
+            cs.enable_equality(column);
+        }
+        cs.enable_constant(constant);
+
+        let t_columns = active.clone();
+        cs.create_gate("active", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(selector);
+            let t: Vec<Expression<F>> = t_columns.iter().map(|column| cs.query_advice(*column, Rotation::cur())).collect();
+            let i_in: Expression<F> = cs.query_advice(index[0], Rotation::cur());
+            let i_out: Expression<F> = cs.query_advice(index[1], Rotation::cur());
+            let j: Expression<F> = cs.query_fixed(step);
+            let one: Expression<F> = Expression::Constant(F::ONE);
+
+            let mut constraints = vec![];
+            for (i, t_i) in t.iter().enumerate() {
+                constraints.push(s.clone()*t_i.clone()*(one.clone() - t_i.clone()));
+                if i > 0 {
+                    constraints.push(s.clone()*t_i.clone()*(one.clone() - t[i - 1].clone()));
+                }
+            }
+            let count = t.iter().fold(i_in.clone(), |acc, t| acc + t.clone());
+            constraints.push(s.clone()*(count - i_out));
+            constraints.push(s*t[0].clone()*(i_in - j));
+            constraints
+        });
+
+        //rows without the range selector look up 0, which is always in the table
+        let range = range.then(|| {
+            let table: TableColumn = cs.lookup_table_column();
+            let q_range: Selector = cs.complex_selector();
+            cs.lookup(|cs: &mut VirtualCells<'_, F>| {
+                let q: Expression<F> = cs.query_selector(q_range);
+                let k: Expression<F> = cs.query_advice(index[1], Rotation::cur());
+
+                vec![(q*k, table)]
+            });
+            (table, q_range)
+        });
+
+        ActiveIndexConfig{
+            active,
+            index,
+            step,
+            range,
+        }
+    }
+
+    //fill the lookup table with 0..=max_k, if there is one
+    pub fn load_table(&self, mut layouter: impl Layouter<F>) -> Result<(), Error> {
+        let Some((column, _)) = self.config.range else {
+            return Ok(());
+        };
+        layouter.assign_table(
+            || "valid k",
+            |mut table| {
+                for offset in 0..=self.max_k {
+                    table.assign_cell(|| "k", column, offset, || Value::known(F::from(offset as u64)))?;
+                }
+                Ok(())
+            }
+        )
+    }
+
+    /*
+    @note
+    •   Assigns the active bits of a row whose terms start after the first ones, the
+        step j = first and the count, continued from prev (or starting at 0), and
+        returns the i_out cell.
+    */
+    pub fn assign(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        first: usize,
+        active: &[Value<F>],
+        prev: Option<&AssignedCell<F, F>>,
+    ) -> Result<AssignedCell<F, F>, Error> {
+        if let Some((_, q_range)) = self.config.range {
+            if first < self.max_k && self.max_k <= first + active.len() {
+                q_range.enable(region, offset)?;
+            }
+        }
+
+        let mut count = Value::known(F::ZERO);
+        for (column, t) in self.config.active.iter().zip(active) {
+            region.assign_advice(|| "active", *column, offset, || *t)?;
+            count = count + t;
+        }
+
+        //j is public: it only depends on the position of the row
+        region.assign_fixed(|| "step", self.config.step, offset, || Value::known(F::from(first as u64)))?;
+
+        let i_in = match prev {
+            Some(prev) => prev.copy_advice(|| "index in = prev index out", region, self.config.index[0], offset)?,
+            None => region.assign_advice_from_constant(|| "index in", self.config.index[0], offset, F::ZERO)?,
+        };
+        region.assign_advice(
+            || "index out",
+            self.config.index[1],
+            offset,
+            || i_in.value().copied() + count
+        )
+    }
+}
+
+impl<F: PrimeField> Chip<F> for ActiveIndexChip<F>{
+    type Config = ActiveIndexConfig;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config{
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded{
+        &()
+    }
+}
+
+/*
+@note
+•   max_k is part of the chip rather than the config: the config is fixed by the
+    constraint system, the number of rows is picked by the circuit using the chip.
+*/
+pub struct LinearRecurrenceChip<F: PrimeField, const N: usize>{
+    config: LinearRecurrenceConfig<F, N>,
+    active_index: ActiveIndexChip<F>,
+    max_k: usize,
+}
+
+impl<F: PrimeField, const N: usize> LinearRecurrenceChip<F, N>{
+    pub fn construct(cnfg: LinearRecurrenceConfig<F, N>, max_k: usize) -> Self{
+        Self{
+            active_index: ActiveIndexChip::construct(cnfg.active_index.clone(), max_k),
+            config: cnfg,
+            max_k,
+        }
+    }
+
+    pub fn max_k(&self) -> usize{
+        self.max_k
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn configure(
+        cs: &mut ConstraintSystem<F>,
+        coefficients: [F; N],
+        inputs: [Column<Advice>; N],
+        output: Column<Advice>,
+        active: Column<Advice>,
+        index: [Column<Advice>; 2],
+        step: Column<Fixed>,
+        constant: Column<Fixed>,
+    ) -> LinearRecurrenceConfig<F, N> {
+        let selector: Selector = cs.selector();
+        let seed: Selector = cs.selector();
+        for column in inputs.iter().chain([&output]) {
+            cs.enable_equality(*column);
+        }
+
+        /*
+        @note
+        •   In every active row y = Σ c_i·x_N-i, and in every inactive row y = x_N-1.
+        •   The gate keeps the name "add" it has in the fib circuit.
+        */
+        cs.create_gate("add", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(selector);
+            let x: Vec<Expression<F>> = inputs.iter().map(|column| cs.query_advice(*column, Rotation::cur())).collect();
+            let y: Expression<F> = cs.query_advice(output, Rotation::cur());
+            let t: Expression<F> = cs.query_advice(active, Rotation::cur());
+            let one: Expression<F> = Expression::Constant(F::ONE);
+
+            //c_1 goes with f(n-1), the last input
+            let sum = x.iter().rev().zip(coefficients).fold(Expression::Constant(F::ZERO), |acc, (x, c)| {
+                acc + Expression::Constant(c)*x.clone()
+            });
+            let last = x[N - 1].clone();
+
+            vec![s*(t.clone()*sum + (one - t)*last - y)]
+        });
+
+        seed_gate(cs, seed, coefficients, inputs, output);
+        let active_index = ActiveIndexChip::configure(cs, selector, vec![active], index, step, constant, false);
+
+        LinearRecurrenceConfig{
+            coefficients,
+            inputs,
+            output,
+            active_index,
+            selector,
+            seed,
+        }
+    }
+
+    /*
+    @note
+    •   Lays out the N-1 seed rows for f(0)..f(N-1), each one copying its window from
+        the previous one (f(m-1) in front, f(m+N-1) dropped).
+    */
+    pub fn assign_seeds(
+        &self,
+        mut layouter: impl Layouter<F>,
+        trace: Value<&Trace<F, N>>,
+    ) -> Result<SeedRows<F, N>, Error> {
+        //c_N != 0, otherwise there is no window before f(0) (see Trace::new)
+        if bool::from(self.config.coefficients[N - 1].is_zero()) {
+            return Err(Error::Synthesis);
+        }
+        layouter.assign_region(
+            || "seeds",
+            |mut region| {
+                let mut window = self.assign_inputs(&mut region, 0, trace.map(|t| t.seeds()).transpose_array(), None)?;
+                let seeds = window.clone();
+                for row in 0..N - 1 {
+                    self.config.seed.enable(&mut region, row)?;
+                    //the row holding f(m)..f(m+N-1) outputs f(m-1)
+                    let m = -(row as isize);
+                    let y = region.assign_advice(|| "f_m-1", self.config.output, row, || trace.map(|t| t.term(m - 1)))?;
+                    let next: [AssignedCell<F, F>; N] = std::array::from_fn(|i| if i == 0 {
+                        y.clone()
+                    } else {
+                        window[i - 1].clone()
+                    });
+                    window = if row + 1 < N - 1 {
+                        let values = trace.map(|t| std::array::from_fn(|i| t.term(m - 1 + i as isize)));
+                        self.assign_inputs(&mut region, row + 1, values.transpose_array(), Some(&next))?
+                    } else {
+                        next
+                    };
+                }
+                Ok(SeedRows{
+                    seeds,
+                    window,
+                })
+            }
+        )
+    }
+
+    //assign the inputs x_0..x_N-1 at offset, copy-constrained to window if given
+    fn assign_inputs(
+        &self,
+        region: &mut Region<'_, F>,
+        offset: usize,
+        values: [Value<F>; N],
+        window: Option<&[AssignedCell<F, F>; N]>,
+    ) -> Result<[AssignedCell<F, F>; N], Error> {
+        let mut cells = Vec::with_capacity(N);
+        for (i, (column, value)) in self.config.inputs.iter().zip(values).enumerate() {
+            let cell = region.assign_advice(|| format!("x_{}", i), *column, offset, || value)?;
+            if let Some(window) = window {
+                region.constrain_equal(cell.cell(), window[i].cell())?;
+            }
+            cells.push(cell);
+        }
+        cells.try_into().map_err(|_| Error::Synthesis)
+    }
+
+    /*
+    @note
+    •   Assigns the row producing f(n), n in 1..=max_k, with inputs copied from window
+        (the previous row, or the seed rows for n = 1). The values are assigned as given
+        (see Trace::inputs and Trace::output), so a row that doesn't continue the previous
+        one fails verification instead of being silently patched up here.
+    •   The active bit, index and step j = n - 1 go through the ActiveIndexChip.
+    */
+    #[allow(clippy::too_many_arguments)]
+    pub fn assign_row(
+        &self,
+        mut layouter: impl Layouter<F>,
+        n: usize,
+        inputs: [Value<F>; N],
+        active: Value<F>,
+        output: Value<F>,
+        window: &[AssignedCell<F, F>; N],
+        prev: Option<&RecurrenceRow<F, N>>,
+    ) -> Result<RecurrenceRow<F, N>, Error> {
+        layouter.assign_region(
+            || "row",
+            |mut region| {
+                self.config.selector.enable(&mut region, 0)?;
+
+                let inputs = self.assign_inputs(&mut region, 0, inputs, Some(window))?;
+
+                let index = self.active_index.assign(&mut region, 0, n - 1, &[active], prev.map(|prev| &prev.index))?;
+
+                let output = region.assign_advice(|| format!("f_{}", n), self.config.output, 0, || output)?;
+
+                Ok(RecurrenceRow{
+                    n,
+                    inputs,
+                    output,
+                    index,
+                })
+            }
+        )
+    }
+
+    /*
+    @note
+    •   Lays out the seed rows for f(0)..f(N-1) and the max_k rows for the hidden k of
+        the trace, and returns the seed cells and the last row, whose output is f(k).
+    •   max_k = 0 leaves no row to hold f(k), so it is rejected.
+    */
+    #[allow(clippy::type_complexity)]
+    pub fn assign(
+        &self,
+        mut layouter: impl Layouter<F>,
+        trace: Value<&Trace<F, N>>,
+    ) -> Result<(SeedRows<F, N>, RecurrenceRow<F, N>), Error> {
+        let seeds = self.assign_seeds(layouter.namespace(|| "seeds"), trace)?;
+        let mut window = seeds.window.clone();
+        let mut prev: Option<RecurrenceRow<F, N>> = None;
+        for n in 1..=self.max_k {
+            let row = self.assign_row(
+                layouter.namespace(|| format!("assign f_{}", n)),
+                n,
+                trace.map(|t| t.inputs(n)).transpose_array(),
+                trace.map(|t| t.active(n)),
+                trace.map(|t| t.output(n)),
+                &window,
+                prev.as_ref(),
+            )?;
+            window = row.window();
+            prev = Some(row);
+        }
+        Ok((seeds, prev.ok_or(Error::Synthesis)?))
+    }
+}
+
+/*
+@note
+•   The window x_0..x_N-1 = f(m)..f(m+N-1) and y = f(m-1) satisfy the recurrence
+    for f(m+N-1), i.e. c_N·y + c_N-1·x_0 + ... + c_1·x_N-2 = x_N-1.
+•   Shared with the packed chip, whose seed row is the N = 2 case.
+*/
+pub(crate) fn seed_gate<F: Field, const N: usize>(
+    cs: &mut ConstraintSystem<F>,
+    seed: Selector,
+    coefficients: [F; N],
+    inputs: [Column<Advice>; N],
+    output: Column<Advice>,
+){
+    cs.create_gate("seed", |cs: &mut VirtualCells<'_, F>| {
+        let s: Expression<F> = cs.query_selector(seed);
+        let x: Vec<Expression<F>> = inputs.iter().map(|column| cs.query_advice(*column, Rotation::cur())).collect();
+        let y: Expression<F> = cs.query_advice(output, Rotation::cur());
+
+        let sum = x[..N - 1].iter().rev().zip(coefficients).fold(
+            Expression::Constant(coefficients[N - 1])*y,
+            |acc, (x, c)| acc + Expression::Constant(c)*x.clone(),
+        );
+
+        vec![s*(sum - x[N - 1].clone())]
+    });
+}
+
+impl<F: PrimeField, const N: usize> Chip<F> for LinearRecurrenceChip<F, N>{
+    type Config = LinearRecurrenceConfig<F, N>;
+    type Loaded = ();
+
+    fn config(&self) -> &Self::Config{
+        &self.config
+    }
+
+    fn loaded(&self) -> &Self::Loaded{
+        &()
+    }
+}
+
+/*
+@note
+•   A named sequence: its coefficients [c_1..c_N] and seeds f(0)..f(N-1).
+*/
+pub trait Sequence<const N: usize>{
+    const COEFFICIENTS: [u64; N];
+    const SEEDS: [u64; N];
+}
+
+//2, 1, 3, 4, 7, 11, ...
+pub struct Lucas;
+
+impl Sequence<2> for Lucas{
+    const COEFFICIENTS: [u64; 2] = [1, 1];
+    const SEEDS: [u64; 2] = [2, 1];
+}
+
+//0, 1, 2, 5, 12, 29, ...
+pub struct Pell;
+
+impl Sequence<2> for Pell{
+    const COEFFICIENTS: [u64; 2] = [2, 1];
+    const SEEDS: [u64; 2] = [0, 1];
+}
+
+//0, 1, 1, 3, 5, 11, ...
+pub struct Jacobsthal;
+
+impl Sequence<2> for Jacobsthal{
+    const COEFFICIENTS: [u64; 2] = [1, 2];
+    const SEEDS: [u64; 2] = [0, 1];
+}
+
+//0, 0, 1, 1, 2, 4, 7, ...
+pub struct Tribonacci;
+
+impl Sequence<3> for Tribonacci{
+    const COEFFICIENTS: [u64; 3] = [1, 1, 1];
+    const SEEDS: [u64; 3] = [0, 0, 1];
+}
+
+#[derive(Clone, Debug)]
+pub struct SequenceConfig<F: Field, const N: usize>{
+    recurrence: LinearRecurrenceConfig<F, N>,
+    instance: Column<Instance>,
+}
+
+/*
+@note
+•   Proves f(k) = z for the sequence S with k hidden below the public bound max_k.
+    The seeds are fixed by S, so they are pinned to constants and only z is public.
+*/
+pub struct SequenceCircuit<F: PrimeField, S: Sequence<N>, const N: usize>{
+    pub k: Value<usize>,
+    pub z: Value<F>,
+    pub max_k: usize,
+    _marker: PhantomData<S>,
+}
+
+pub type LucasCircuit<F> = SequenceCircuit<F, Lucas, 2>;
+pub type PellCircuit<F> = SequenceCircuit<F, Pell, 2>;
+pub type JacobsthalCircuit<F> = SequenceCircuit<F, Jacobsthal, 2>;
+pub type TribonacciCircuit<F> = SequenceCircuit<F, Tribonacci, 3>;
+
+impl<F: PrimeField, S: Sequence<N>, const N: usize> SequenceCircuit<F, S, N>{
+    pub fn new(k: usize, z: F, max_k: usize) -> Self{
+        Self{
+            k: Value::known(k),
+            z: Value::known(z),
+            max_k,
+            _marker: PhantomData,
+        }
+    }
+
+    //same as FibCircuit::check: at least one row, and a known k can't be past max_k
+    pub fn check(&self) -> Result<(), FibError>{
+        if self.max_k == 0 {
+            return Err(FibError::NotEnoughRows{ max_k: self.max_k });
+        }
+        let mut result = Ok(());
+        let _ = self.k.map(|k| if k > self.max_k {
+            result = Err(FibError::InvalidIndex{ k, max_k: self.max_k });
+        });
+        result
+    }
+
+    //public inputs matching the instance column: [z], empty if z is unknown
+    pub fn instances(&self) -> Vec<F>{
+        let mut instances = vec![];
+        let _ = self.z.map(|z| instances.push(z));
+        instances
+    }
+}
+
+impl<F: PrimeField, S: Sequence<N>, const N: usize> Circuit<F> for SequenceCircuit<F, S, N>{
+    type Config = SequenceConfig<F, N>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            k: Value::unknown(),
+            z: Value::unknown(),
+            max_k: self.max_k,
+            _marker: PhantomData,
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<F>) -> Self::Config {
+        let inputs = [(); N].map(|_| cs.advice_column());
+        let output = cs.advice_column();
+        let active = cs.advice_column();
+        let index = [cs.advice_column(), cs.advice_column()];
+        let step = cs.fixed_column();
+        let constant = cs.fixed_column();
+        let instance = cs.instance_column();
+        cs.enable_equality(instance);
+        SequenceConfig{
+            recurrence: LinearRecurrenceChip::configure(
+                cs,
+                S::COEFFICIENTS.map(F::from),
+                inputs,
+                output,
+                active,
+                index,
+                step,
+                constant,
+            ),
+            instance,
+        }
+    }
+
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<F>) -> Result<(), Error>{
+        let chip: LinearRecurrenceChip<F, N> = LinearRecurrenceChip::construct(config.recurrence, self.max_k);
+        //synthesize can only report a bare halo2 error, check() has the details
+        self.check().map_err(|_| Error::Synthesis)?;
+        let trace = self.k.map(|k| Trace::new(S::COEFFICIENTS.map(F::from), S::SEEDS.map(F::from), k));
+        let (seeds, last) = chip.assign(layouter.namespace(|| "sequence"), trace.as_ref())?;
+        layouter.assign_region(
+            || "pin seeds",
+            |mut region| {
+                for (cell, seed) in seeds.seeds.iter().zip(S::SEEDS) {
+                    region.constrain_constant(cell.cell(), F::from(seed))?;
+                }
+                Ok(())
+            }
+        )?;
+        layouter.constrain_instance(last.output.cell(), config.instance, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::witness::{fib, sequence};
+    use halo2_proofs::{dev::MockProver, pasta::{Fp, Fq}};
+
+    //f(k) over the integers
+    fn nth<S: Sequence<N>, const N: usize>(k: usize) -> u64{
+        let mut terms = S::SEEDS.to_vec();
+        while terms.len() <= k {
+            let n = terms.len();
+            terms.push((1..=N).map(|i| S::COEFFICIENTS[i - 1]*terms[n - i]).sum());
+        }
+        terms[k]
+    }
+
+    //f(k) over F
+    fn term<F: PrimeField, S: Sequence<N>, const N: usize>(k: usize) -> F{
+        sequence(S::COEFFICIENTS.map(F::from), S::SEEDS.map(F::from)).nth(k).unwrap()
+    }
+
+    fn check<F: PrimeField + Ord, S: Sequence<N>, const N: usize>(k: usize, z: F, max_k: usize) -> bool{
+        let circ: SequenceCircuit<F, S, N> = SequenceCircuit::new(k, z, max_k);
+        MockProver::run(8, &circ, vec![circ.instances()]).unwrap().verify().is_ok()
+    }
+
+    fn test_field<F: PrimeField + Ord, S: Sequence<N>, const N: usize>(expected: &[u64]){
+        for (k, z) in expected.iter().enumerate() {
+            assert_eq!(term::<F, S, N>(k), F::from(*z));
+        }
+        for k in [0, 1, N - 1, N, 7, 12] {
+            assert!(check::<F, S, N>(k, term::<F, S, N>(k), 12));
+            assert!(!check::<F, S, N>(k, term::<F, S, N>(k) + F::ONE, 12));
+        }
+    }
+
+    fn test_sequence<S: Sequence<N>, const N: usize>(expected: &[u64]){
+        for (k, z) in expected.iter().enumerate() {
+            assert_eq!(nth::<S, N>(k), *z);
+        }
+        test_field::<Fp, S, N>(expected);
+        test_field::<Fq, S, N>(expected);
+    }
+
+    #[test]
+    fn test_lucas(){
+        test_sequence::<Lucas, 2>(&[2, 1, 3, 4, 7, 11, 18, 29]);
+    }
+
+    #[test]
+    fn test_pell(){
+        test_sequence::<Pell, 2>(&[0, 1, 2, 5, 12, 29, 70, 169]);
+    }
+
+    #[test]
+    fn test_jacobsthal(){
+        test_sequence::<Jacobsthal, 2>(&[0, 1, 1, 3, 5, 11, 21, 43]);
+    }
+
+    #[test]
+    fn test_tribonacci(){
+        test_sequence::<Tribonacci, 3>(&[0, 0, 1, 1, 2, 4, 7, 13, 24]);
+    }
+
+    /*
+    @note
+    •   The seeds are pinned, so a prover can't pass off another sequence's term:
+        f(7) = 29 for Lucas but 70 for Pell.
+    */
+    #[test]
+    fn test_seeds_pinned(){
+        assert!(check::<Fp, Lucas, 2>(7, Fp::from(29), 12));
+        assert!(!check::<Fp, Fibonacci, 2>(7, Fp::from(29), 12));
+        assert!(!check::<Fp, Pell, 2>(7, Fp::from(29), 12));
+    }
+
+    #[test]
+    fn test_check(){
+        assert!(LucasCircuit::<Fp>::new(12, term::<Fp, Lucas, 2>(12), 12).check().is_ok());
+        let circ = |k, max_k| TribonacciCircuit::<Fp>::new(k, Fp::ZERO, max_k);
+        assert!(matches!(circ(13, 12).check(), Err(FibError::InvalidIndex{ k: 13, max_k: 12 })));
+        assert!(matches!(circ(0, 0).check(), Err(FibError::NotEnoughRows{ max_k: 0 })));
+
+        //synthesize refuses the claim before building a trace for k
+        for k in [13, usize::MAX] {
+            assert!(matches!(MockProver::run(8, &circ(k, 12), vec![circ(k, 12).instances()]), Err(Error::Synthesis)), "k = {}", k);
+        }
+    }
+
+    //past what fits a u64 the native sequence still agrees with fib, on both sides of the cycle
+    #[test]
+    fn test_native(){
+        let (x, y) = (Fq::from(3), -Fq::from(7));
+        let terms: Vec<Fq> = sequence([Fq::ONE; 2], [x, y]).take(300).collect();
+        assert_eq!(terms, (0..300).map(|k| fib(x, y, k)).collect::<Vec<_>>());
+        assert_eq!(sequence([Fp::ONE; 2], [Fp::ZERO, Fp::ONE]).nth(300), Some(fib(Fp::ZERO, Fp::ONE, 300)));
+    }
+
+    //plain fibonacci seeded with 1, 1 (f(7) = 21)
+    struct Fibonacci;
+
+    impl Sequence<2> for Fibonacci{
+        const COEFFICIENTS: [u64; 2] = [1, 1];
+        const SEEDS: [u64; 2] = [1, 1];
+    }
+}
@@ -0,0 +1,508 @@
+use halo2_proofs::{
+    circuit::{AssignedCell, Layouter, Value},
+    pasta::group::ff::{FromUniformBytes, PrimeField},
+    poly::{Rotation},
+    plonk::{
+        Advice, ConstraintSystem,
+        Column, Fixed, Error,
+        Selector, Expression,
+        VirtualCells,
+    },
+};
+use std::marker::PhantomData;
+
+/*
+@note
+•   Poseidon with the P128Pow5T3 parameters (the ones used by Orchard):
+    width 3, rate 2, x^5 S-box, 8 full rounds and 56 partial rounds, with
+    round constants and MDS matrix derived from the Grain LFSR like the
+    reference implementation, so the native hash matches halo2_gadgets for
+    Pasta Fp and Fq.
+•   Hashing uses the ConstantLength<L> domain: the capacity element starts
+    at L << 64 and the message is zero-padded to a multiple of the rate.
+*/
+pub const WIDTH: usize = 3;
+pub const RATE: usize = 2;
+const FULL_ROUNDS: usize = 8;
+const PARTIAL_ROUNDS: usize = 56;
+const ROUNDS: usize = FULL_ROUNDS + PARTIAL_ROUNDS;
+
+pub type Mds<F> = [[F; WIDTH]; WIDTH];
+
+//fields the constants can be derived for
+pub trait PoseidonField: PrimeField + FromUniformBytes<64> {}
+
+impl<F: PrimeField + FromUniformBytes<64>> PoseidonField for F {}
+
+/*
+@note
+•   Grain LFSR in self-shrinking mode, seeded with the parameters of the instance
+    (prime field, x^alpha S-box, field size, width, full rounds, partial rounds).
+*/
+struct Grain<F: PoseidonField>{
+    state: [bool; 80],
+    next_bit: usize,
+    _marker: PhantomData<F>,
+}
+
+impl<F: PoseidonField> Grain<F>{
+    fn new() -> Self{
+        let mut state = [true; 80];
+        let mut set_bits = |offset: usize, len: usize, value: u16| {
+            for i in 0..len {
+                state[offset + len - 1 - i] = (value >> i) & 1 != 0;
+            }
+        };
+        //prime field, x^alpha S-box
+        set_bits(0, 2, 1);
+        set_bits(2, 4, 0);
+        set_bits(6, 12, F::NUM_BITS as u16);
+        set_bits(18, 12, WIDTH as u16);
+        set_bits(30, 10, FULL_ROUNDS as u16);
+        set_bits(40, 10, PARTIAL_ROUNDS as u16);
+
+        let mut grain = Grain{
+            state,
+            next_bit: 80,
+            _marker: PhantomData,
+        };
+        //discard the first 160 bits
+        for _ in 0..20 {
+            grain.load_next_8_bits();
+            grain.next_bit = 80;
+        }
+        grain
+    }
+
+    fn load_next_8_bits(&mut self){
+        let mut new_bits = [false; 8];
+        for (i, bit) in new_bits.iter_mut().enumerate() {
+            *bit = self.state[i + 62]
+                ^ self.state[i + 51]
+                ^ self.state[i + 38]
+                ^ self.state[i + 23]
+                ^ self.state[i + 13]
+                ^ self.state[i];
+        }
+        self.state.rotate_left(8);
+        self.next_bit -= 8;
+        self.state[self.next_bit..self.next_bit + 8].copy_from_slice(&new_bits);
+    }
+
+    fn get_next_bit(&mut self) -> bool{
+        if self.next_bit == 80 {
+            self.load_next_8_bits();
+        }
+        let bit = self.state[self.next_bit];
+        self.next_bit += 1;
+        bit
+    }
+
+    //self-shrinking: a pair (1, b) outputs b, a pair (0, b) is dropped
+    fn next_bit(&mut self) -> bool{
+        while !self.get_next_bit() {
+            self.get_next_bit();
+        }
+        self.get_next_bit()
+    }
+
+    //NUM_BITS bits, most significant first, written into a little-endian buffer
+    fn fill(&mut self, bytes: &mut [u8]){
+        let num_bits = F::NUM_BITS as usize;
+        for i in 0..num_bits {
+            let bit = self.next_bit();
+            let i = num_bits - 1 - i;
+            bytes[i / 8] |= (bit as u8) << (i % 8);
+        }
+    }
+
+    //round constants: sample until the value is a canonical field element
+    fn next_field_element(&mut self) -> F{
+        loop {
+            let mut repr = F::Repr::default();
+            self.fill(repr.as_mut());
+            if let Some(f) = Option::from(F::from_repr(repr)) {
+                break f;
+            }
+        }
+    }
+
+    //MDS entries: reduce the sampled value instead of rejecting it
+    fn next_field_element_without_rejection(&mut self) -> F{
+        let mut bytes = [0u8; 64];
+        self.fill(&mut bytes);
+        F::from_uniform_bytes(&bytes)
+    }
+}
+
+//round constants (one row per round) and the Cauchy MDS matrix
+pub fn constants<F: PoseidonField>() -> (Vec<[F; WIDTH]>, Mds<F>){
+    let mut grain = Grain::<F>::new();
+
+    let round_constants = (0..ROUNDS)
+        .map(|_| [(); WIDTH].map(|_| grain.next_field_element()))
+        .collect();
+
+    //sample xs and ys until all 2 * WIDTH values are distinct, then M_ij = 1 / (x_i + y_j)
+    let (xs, ys) = loop {
+        let vals: Vec<F> = (0..2 * WIDTH).map(|_| grain.next_field_element_without_rejection()).collect();
+        let distinct = (0..vals.len()).all(|i| (i + 1..vals.len()).all(|j| vals[i] != vals[j]));
+        if distinct {
+            break (vals[..WIDTH].to_vec(), vals[WIDTH..].to_vec());
+        }
+    };
+    let mut mds = [[F::ZERO; WIDTH]; WIDTH];
+    for (i, row) in mds.iter_mut().enumerate() {
+        for (j, entry) in row.iter_mut().enumerate() {
+            *entry = (xs[i] + ys[j]).invert().unwrap();
+        }
+    }
+
+    (round_constants, mds)
+}
+
+fn sbox<F: PoseidonField>(x: F) -> F{
+    x.square().square() * x
+}
+
+fn apply_mds<F: PoseidonField>(mds: &Mds<F>, state: &[F; WIDTH]) -> [F; WIDTH]{
+    let mut next = [F::ZERO; WIDTH];
+    for (i, word) in next.iter_mut().enumerate() {
+        for j in 0..WIDTH {
+            *word += mds[i][j] * state[j];
+        }
+    }
+    next
+}
+
+fn is_full_round(round: usize) -> bool{
+    !(FULL_ROUNDS / 2..FULL_ROUNDS / 2 + PARTIAL_ROUNDS).contains(&round)
+}
+
+//one round of the permutation
+fn round<F: PoseidonField>(mds: &Mds<F>, rc: &[F; WIDTH], state: &[F; WIDTH], full: bool) -> [F; WIDTH]{
+    let mut words = [F::ZERO; WIDTH];
+    for i in 0..WIDTH {
+        words[i] = state[i] + rc[i];
+        if full || i == 0 {
+            words[i] = sbox(words[i]);
+        }
+    }
+    apply_mds(mds, &words)
+}
+
+pub fn permute<F: PoseidonField>(state: &mut [F; WIDTH]){
+    let (round_constants, mds) = constants::<F>();
+    for (r, rc) in round_constants.iter().enumerate() {
+        *state = round(&mds, rc, state, is_full_round(r));
+    }
+}
+
+fn initial_capacity<F: PoseidonField>(len: usize) -> F{
+    F::from_u128((len as u128) << 64)
+}
+
+//Poseidon hash of a fixed-length message (ConstantLength<L>)
+pub fn hash<F: PoseidonField, const L: usize>(message: [F; L]) -> F{
+    let mut state = [F::ZERO; WIDTH];
+    state[RATE] = initial_capacity(L);
+    for chunk in message.chunks(RATE) {
+        for (word, m) in state.iter_mut().zip(chunk) {
+            *word += m;
+        }
+        permute(&mut state);
+    }
+    state[0]
+}
+
+/*
+@note
+•   In-circuit version. The whole hash lives in one region, one row per round:
+
+    s_0 | s_1 | s_2 | m_0 | m_1 | rc_0 | rc_1 | rc_2 | selector
+    ----------------------------------------------------------
+    0     0     L<<64  k     z                          absorb
+    k     z     L<<64              rc[0]                full
+    ...                                                 (64 rounds)
+    s'_0  s'_1  s'_2   r     0                          absorb
+    ...                                                 (64 rounds)
+    h
+
+•   Full rounds: s_next = M * (s + rc)^5, partial rounds only raise s_0 + rc_0 to the 5th.
+    The gates look at the next row (Rotation::next()).
+*/
+#[derive(Clone, Debug)]
+pub struct PoseidonConfig<F: PoseidonField>{
+    state: [Column<Advice>; WIDTH],
+    message: [Column<Advice>; RATE],
+    rc: [Column<Fixed>; WIDTH],
+    s_absorb: Selector,
+    s_full: Selector,
+    s_partial: Selector,
+    round_constants: Vec<[F; WIDTH]>,
+}
+
+pub struct PoseidonChip<F: PoseidonField>{
+    config: PoseidonConfig<F>,
+}
+
+impl<F: PoseidonField> PoseidonChip<F>{
+    pub fn construct(cnfg: PoseidonConfig<F>) -> Self{
+        Self{
+            config: cnfg,
+        }
+    }
+
+    pub fn configure(
+        cs: &mut ConstraintSystem<F>,
+        state: [Column<Advice>; WIDTH],
+        message: [Column<Advice>; RATE],
+        constant: Column<Fixed>,
+    ) -> PoseidonConfig<F> {
+        let rc = [cs.fixed_column(), cs.fixed_column(), cs.fixed_column()];
+        let s_absorb: Selector = cs.selector();
+        let s_full: Selector = cs.selector();
+        let s_partial: Selector = cs.selector();
+        let (round_constants, mds) = constants::<F>();
+        for column in state.iter().chain(message.iter()) {
+            cs.enable_equality(*column);
+        }
+        //the initial state is pinned to constants
+        cs.enable_constant(constant);
+
+        //s_next = s + (m_0, m_1, 0)
+        cs.create_gate("absorb", |cs: &mut VirtualCells<'_, F>| {
+            let s: Expression<F> = cs.query_selector(s_absorb);
+            (0..WIDTH).map(|i| {
+                let cur: Expression<F> = cs.query_advice(state[i], Rotation::cur());
+                let next: Expression<F> = cs.query_advice(state[i], Rotation::next());
+                let m: Expression<F> = if i < RATE {
+                    cs.query_advice(message[i], Rotation::cur())
+                } else {
+                    Expression::Constant(F::ZERO)
+                };
+                s.clone()*(cur + m - next)
+            }).collect::<Vec<_>>()
+        });
+
+        let mut round_gate = |name: &'static str, selector: Selector, full: bool| {
+            cs.create_gate(name, |cs: &mut VirtualCells<'_, F>| {
+                let s: Expression<F> = cs.query_selector(selector);
+                let words: Vec<Expression<F>> = (0..WIDTH).map(|i| {
+                    let cur: Expression<F> = cs.query_advice(state[i], Rotation::cur());
+                    let rc: Expression<F> = cs.query_fixed(rc[i]);
+                    let word = cur + rc;
+                    if full || i == 0 {
+                        word.clone()*word.clone()*word.clone()*word.clone()*word
+                    } else {
+                        word
+                    }
+                }).collect();
+                (0..WIDTH).map(|i| {
+                    let next: Expression<F> = cs.query_advice(state[i], Rotation::next());
+                    let mixed = (0..WIDTH).fold(Expression::Constant(F::ZERO), |acc, j| {
+                        acc + Expression::Constant(mds[i][j])*words[j].clone()
+                    });
+                    s.clone()*(mixed - next)
+                }).collect::<Vec<_>>()
+            });
+        };
+        round_gate("full round", s_full, true);
+        round_gate("partial round", s_partial, false);
+
+        PoseidonConfig{
+            state,
+            message,
+            rc,
+            s_absorb,
+            s_full,
+            s_partial,
+            round_constants,
+        }
+    }
+
+    //witness a private value the hash can take as input
+    pub fn load_private(&self, mut layouter: impl Layouter<F>, value: Value<F>) -> Result<AssignedCell<F, F>, Error> {
+        layouter.assign_region(
+            || "load private",
+            |mut region| region.assign_advice(|| "private", self.config.message[0], 0, || value)
+        )
+    }
+
+    //ConstantLength<L> hash of the given cells
+    pub fn hash<const L: usize>(
+        &self,
+        mut layouter: impl Layouter<F>,
+        message: [AssignedCell<F, F>; L],
+    ) -> Result<AssignedCell<F, F>, Error> {
+        let (_, mds) = constants::<F>();
+        layouter.assign_region(
+            || "poseidon",
+            |mut region| {
+                let initial = [F::ZERO, F::ZERO, initial_capacity(L)];
+                for (i, word) in initial.iter().enumerate() {
+                    region.assign_advice_from_constant(|| "initial state", self.config.state[i], 0, *word)?;
+                }
+                let mut state = initial.map(Value::known);
+
+                let mut row = 0;
+                let mut output = None;
+                for chunk in message.chunks(RATE) {
+                    //absorb the next chunk (zero-padded)
+                    self.config.s_absorb.enable(&mut region, row)?;
+                    for (i, word) in state.iter_mut().take(RATE).enumerate() {
+                        match chunk.get(i) {
+                            Some(cell) => {
+                                cell.copy_advice(|| "message", &mut region, self.config.message[i], row)?;
+                                *word = *word + cell.value();
+                            }
+                            None => {
+                                region.assign_advice_from_constant(|| "padding", self.config.message[i], row, F::ZERO)?;
+                            }
+                        }
+                    }
+                    row += 1;
+                    for (i, word) in state.iter().enumerate() {
+                        region.assign_advice(|| "absorbed", self.config.state[i], row, || *word)?;
+                    }
+
+                    //one row per round
+                    for (r, rc) in self.config.round_constants.iter().enumerate() {
+                        let full = is_full_round(r);
+                        if full {
+                            self.config.s_full.enable(&mut region, row)?;
+                        } else {
+                            self.config.s_partial.enable(&mut region, row)?;
+                        }
+                        for (column, rc) in self.config.rc.iter().zip(rc) {
+                            region.assign_fixed(|| "rc", *column, row, || Value::known(*rc))?;
+                        }
+                        let next = state[0].zip(state[1]).zip(state[2])
+                            .map(|((s0, s1), s2)| round(&mds, rc, &[s0, s1, s2], full));
+                        row += 1;
+                        for (i, word) in state.iter_mut().enumerate() {
+                            *word = next.as_ref().map(|n| n[i]);
+                            let cell = region.assign_advice(|| "state", self.config.state[i], row, || *word)?;
+                            if i == 0 {
+                                output = Some(cell);
+                            }
+                        }
+                    }
+                }
+                output.ok_or(Error::Synthesis)
+            }
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use halo2_proofs::{
+        circuit::SimpleFloorPlanner,
+        dev::MockProver,
+        pasta::{Fp, Fq},
+        plonk::{Circuit, Instance},
+    };
+
+    //from the zcash orchard_poseidon test vectors (permute/fp.py, hash/fp.py)
+    #[test]
+    fn test_permute_vector(){
+        let mut state = [Fp::from(0), Fp::from(1), Fp::from(2)];
+        permute(&mut state);
+        let expected = [
+            [
+                0x56, 0xa4, 0xec, 0x4a, 0x02, 0xbc, 0xb1, 0xae, 0xa0, 0x42, 0xb6, 0xd0,
+                0x71, 0x9a, 0xe6, 0xf7, 0x0f, 0x24, 0x66, 0xf9, 0x64, 0xb3, 0xef, 0x94,
+                0x53, 0xb4, 0x64, 0x0b, 0xcd, 0x6a, 0x52, 0x2a,
+            ],
+            [
+                0x2a, 0xb8, 0xe5, 0x28, 0x96, 0x3e, 0x2a, 0x01, 0xfe, 0xda, 0xd9, 0xbe,
+                0x7f, 0x2e, 0xd4, 0xdc, 0x12, 0x55, 0x3d, 0x34, 0xae, 0x7d, 0xff, 0x76,
+                0x30, 0xa4, 0x4a, 0x8b, 0x56, 0xd1, 0xc5, 0x13,
+            ],
+            [
+                0xdd, 0x9d, 0x4e, 0xd3, 0xa1, 0x29, 0x90, 0x35, 0x7b, 0x2c, 0xa4, 0xbd,
+                0xe1, 0xdf, 0xcf, 0xf7, 0x1a, 0x56, 0x84, 0x79, 0x59, 0xcd, 0x6f, 0x25,
+                0x44, 0x65, 0x97, 0xc6, 0x68, 0xc8, 0x49, 0x0a,
+            ],
+        ];
+        for (word, bytes) in state.iter().zip(expected) {
+            assert_eq!(*word, Fp::from_repr(bytes).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_hash_vector(){
+        let expected = [
+            0x83, 0x58, 0xd7, 0x11, 0xa0, 0x32, 0x9d, 0x38, 0xbe, 0xcd, 0x54, 0xfb, 0xa7,
+            0xc2, 0x83, 0xed, 0x3e, 0x08, 0x9a, 0x39, 0xc9, 0x1b, 0x6a, 0x9d, 0x10, 0xef,
+            0xb0, 0x2b, 0xc3, 0xf1, 0x2f, 0x06,
+        ];
+        assert_eq!(hash([Fp::from(0), Fp::from(1)]), Fp::from_repr(expected).unwrap());
+    }
+
+    #[test]
+    fn test_mds_invertible(){
+        //a Cauchy matrix with distinct x_i, y_j is invertible, check the determinant anyway
+        fn det<F: PoseidonField>(m: Mds<F>) -> F{
+            m[0][0]*(m[1][1]*m[2][2] - m[1][2]*m[2][1])
+                - m[0][1]*(m[1][0]*m[2][2] - m[1][2]*m[2][0])
+                + m[0][2]*(m[1][0]*m[2][1] - m[1][1]*m[2][0])
+        }
+        assert_ne!(det(constants::<Fp>().1), Fp::zero());
+        assert_ne!(det(constants::<Fq>().1), Fq::zero());
+    }
+
+    /*
+    @note
+    •   Hashes three private values and exposes the digest.
+    */
+    #[derive(Default)]
+    struct HashCircuit{
+        message: [Value<Fp>; 3],
+    }
+
+    impl Circuit<Fp> for HashCircuit{
+        type Config = (PoseidonConfig<Fp>, Column<Instance>);
+        type FloorPlanner = SimpleFloorPlanner;
+
+        fn without_witnesses(&self) -> Self{
+            Self::default()
+        }
+
+        fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+            let state = [cs.advice_column(), cs.advice_column(), cs.advice_column()];
+            let message = [cs.advice_column(), cs.advice_column()];
+            let constant = cs.fixed_column();
+            let instance = cs.instance_column();
+            cs.enable_equality(instance);
+            (PoseidonChip::configure(cs, state, message, constant), instance)
+        }
+
+        fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+            let chip = PoseidonChip::construct(config.0);
+            let mut cells = vec![];
+            for value in self.message {
+                cells.push(chip.load_private(layouter.namespace(|| "message"), value)?);
+            }
+            let message: [AssignedCell<Fp, Fp>; 3] = cells.try_into().unwrap();
+            let digest = chip.hash(layouter.namespace(|| "hash"), message)?;
+            layouter.constrain_instance(digest.cell(), config.1, 0)
+        }
+    }
+
+    #[test]
+    fn test_chip_matches_native(){
+        let message = [Fp::from(9), Fp::from(34), -Fp::from(5)];
+        let circ = HashCircuit{
+            message: message.map(Value::known),
+        };
+
+        let prover = MockProver::run(8, &circ, vec![vec![hash(message)]]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+
+        let prover = MockProver::run(8, &circ, vec![vec![hash(message) + Fp::from(1)]]).unwrap();
+        assert!(prover.verify().is_err());
+    }
+}
@@ -0,0 +1,564 @@
+use halo2_proofs::{
+    arithmetic::Field,
+    circuit::{AssignedCell, Layouter, SimpleFloorPlanner, Value},
+    dev::{FailureLocation, MockProver, VerifyFailure},
+    pasta::{EqAffine, Fp},
+    plonk::{keygen_vk, Any, Circuit, ConstraintSystem, Error},
+    poly::commitment::Params,
+};
+
+use crate::circuits::circuit_naive::{FibCircuit, FibCircuitConfig, Visibility};
+use crate::circuits::poseidon::{self, PoseidonChip};
+use crate::witness::FibTrace;
+
+/*
+@note
+•   Soundness harness for FibCircuit. A malicious prover picks every advice value but
+    not the circuit: the regions, selectors, fixed values and copy constraints are
+    what the keys say. TamperingFibCircuit lays out exactly the FibCircuit layout
+    (test_same_layout checks it against the real verifying key) from a Trace the test
+    is free to edit, so each attack is an edit of an honest trace.
+•   The public inputs are taken from the forged trace, i.e. the prover claims whatever
+    its layout ends in. MockProver then has to pin the attack down to the exact failure:
+    the gate and region that catch it, or the broken copy constraint.
+•   A Tamper changes the layout itself, like a buggy chip would: a cell assigned
+    something else than the trace, a row without its selectors, an input or index
+    that isn't copied from the previous row. The sweeps at the end try every one.
+*/
+
+//columns of a trace row
+const A: usize = 0;
+const B: usize = 1;
+const C: usize = 2;
+const T: usize = 3;
+const I_IN: usize = 4;
+const I_OUT: usize = 5;
+
+#[derive(Clone)]
+struct Trace{
+    //f(0), f(1) and f(-1), the seed row
+    seeds: [Fp; 3],
+    //a, b, c, t, i_in, i_out of the rows for n = 1..=max_k
+    rows: Vec<[Fp; 6]>,
+    r: Fp,
+}
+
+impl Trace{
+    //what an honest prover assigns for f(0) = x, f(1) = y and index k
+    fn honest(x: u64, y: u64, k: usize, max_k: usize) -> Self{
+        let witness = FibTrace::fib(Fp::from(x), Fp::from(y), k);
+        let index = |n: usize| Fp::from(n.min(k) as u64);
+        Trace{
+            seeds: [witness.term(0), witness.term(1), witness.term(-1)],
+            rows: (1..=max_k).map(|n| {
+                let [a, b] = witness.inputs(n);
+                [a, b, witness.output(n), witness.active(n), index(n - 1), index(n)]
+            }).collect(),
+            r: Fp::from(7),
+        }
+    }
+
+    /*
+    @note
+    •   Recomputes the rows from n on, keeping their active bits and the a cell of row n,
+        as a prover would after changing something in row n.
+    */
+    fn rerun(&mut self, n: usize){
+        for i in n - 1..self.rows.len() {
+            let (b, i_in) = match i {
+                0 => (self.seeds[0], Fp::ZERO),
+                _ => (self.rows[i - 1][C], self.rows[i - 1][I_OUT]),
+            };
+            if i > n - 1 {
+                self.rows[i][A] = self.rows[i - 1][B];
+            }
+            self.rows[i][B] = b;
+            self.rows[i][I_IN] = i_in;
+            self.recompute(i + 1);
+        }
+    }
+
+    //c and i_out of row n from its other cells
+    fn recompute(&mut self, n: usize){
+        let row = self.row(n);
+        row[C] = row[T]*(row[A] + row[B]) + (Fp::ONE - row[T])*row[B];
+        row[I_OUT] = row[I_IN] + row[T];
+    }
+
+    //the cell in column of row n, row 0 being the seed row (f(0), f(1), f(-1))
+    fn at(&self, n: usize, column: usize) -> Fp{
+        match n {
+            0 => self.seeds[column],
+            _ => self.rows[n - 1][column],
+        }
+    }
+
+    fn cell(&mut self, n: usize, column: usize) -> &mut Fp{
+        match n {
+            0 => &mut self.seeds[column],
+            _ => &mut self.rows[n - 1][column],
+        }
+    }
+
+    fn row(&mut self, n: usize) -> &mut [Fp; 6]{
+        &mut self.rows[n - 1]
+    }
+
+    //the public inputs of the claim the trace ends in (see FibCircuit::instances)
+    fn instances(&self, visibility: Visibility) -> Vec<Fp>{
+        let last = self.rows[self.rows.len() - 1];
+        let (x, y, k, z) = (self.seeds[0], self.seeds[1], last[I_OUT], last[C]);
+        let digest = poseidon::hash([k, z, self.r]);
+        match visibility {
+            Visibility::PublicK => vec![k, digest],
+            Visibility::PublicZ => vec![x, y, z, digest],
+            Visibility::PublicSeeds => vec![x, y, digest],
+            Visibility::AllPrivateCommitted => vec![digest],
+        }
+    }
+}
+
+/*
+@note
+•   Changes to the layout, row 0 being the seed row and row n the one producing f(n).
+*/
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Tamper{
+    //assign value instead of what the trace holds (column A..I_OUT, A..C on the seed row)
+    Cell{ row: usize, column: usize, value: Fp },
+    //leave every selector of the row off
+    DropSelector{ row: usize },
+    //assign the cell (A, B or I_IN of a row) without copying it from the previous row
+    SkipCopy{ row: usize, column: usize },
+}
+
+struct TamperingFibCircuit{
+    trace: Trace,
+    visibility: Visibility,
+    tampers: Vec<Tamper>,
+}
+
+impl TamperingFibCircuit{
+    fn honest(trace: Trace, visibility: Visibility) -> Self{
+        Self{ trace, visibility, tampers: vec![] }
+    }
+
+    //what the trace holds for the cell, unless a Tamper::Cell overrides it
+    fn value(&self, row: usize, column: usize) -> Value<Fp>{
+        let forged = self.tampers.iter().find_map(|tamper| match *tamper {
+            Tamper::Cell{ row: r, column: c, value } if (r, c) == (row, column) => Some(value),
+            _ => None,
+        });
+        Value::known(forged.unwrap_or_else(|| self.trace.at(row, column)))
+    }
+
+    fn selectors(&self, row: usize) -> bool{
+        !self.tampers.contains(&Tamper::DropSelector{ row })
+    }
+
+    fn copies(&self, row: usize, column: usize) -> bool{
+        !self.tampers.contains(&Tamper::SkipCopy{ row, column })
+    }
+}
+
+impl Circuit<Fp> for TamperingFibCircuit{
+    type Config = FibCircuitConfig<Fp>;
+    type FloorPlanner = SimpleFloorPlanner;
+
+    fn without_witnesses(&self) -> Self{
+        Self{
+            trace: self.trace.clone(),
+            visibility: self.visibility,
+            tampers: self.tampers.clone(),
+        }
+    }
+
+    fn configure(cs: &mut ConstraintSystem<Fp>) -> Self::Config {
+        FibCircuit::configure(cs)
+    }
+
+    //FibChip::assign and FibCircuit::assign_claim, with the values taken from the trace
+    fn synthesize(&self, config: Self::Config, mut layouter: impl Layouter<Fp>) -> Result<(), Error>{
+        let max_k = self.trace.rows.len();
+        let fib = config.fib;
+        let poseidon_chip: PoseidonChip<Fp> = PoseidonChip::construct(config.poseidon);
+
+        let (x, y, prior) = layouter.assign_region(
+            || "seeds",
+            |mut region| {
+                let x = region.assign_advice(|| "x_0", fib.inputs[0], 0, || self.value(0, A))?;
+                let y = region.assign_advice(|| "x_1", fib.inputs[1], 0, || self.value(0, B))?;
+                if self.selectors(0) {
+                    fib.seed.enable(&mut region, 0)?;
+                }
+                let prior = region.assign_advice(|| "f_m-1", fib.output, 0, || self.value(0, C))?;
+                Ok((x, y, prior))
+            }
+        )?;
+
+        let mut window: [AssignedCell<Fp, Fp>; 2] = [prior, x.clone()];
+        let mut index: Option<AssignedCell<Fp, Fp>> = None;
+        let mut output = None;
+        for n in 1..=max_k {
+            let (inputs, i_out, c) = layouter.assign_region(
+                || "row",
+                |mut region| {
+                    if self.selectors(n) {
+                        fib.selector.enable(&mut region, 0)?;
+                    }
+                    let mut inputs = vec![];
+                    for (i, (column, cell)) in fib.inputs.iter().zip(&window).enumerate() {
+                        let input = region.assign_advice(|| "x", *column, 0, || self.value(n, A + i))?;
+                        if self.copies(n, A + i) {
+                            region.constrain_equal(input.cell(), cell.cell())?;
+                        }
+                        inputs.push(input);
+                    }
+                    region.assign_advice(|| "active", fib.active_index.active[0], 0, || self.value(n, T))?;
+                    region.assign_fixed(|| "step", fib.active_index.step, 0, || Value::known(Fp::from(n as u64 - 1)))?;
+                    let i_in = region.assign_advice(|| "index in", fib.active_index.index[0], 0, || self.value(n, I_IN))?;
+                    if self.copies(n, I_IN) {
+                        match &index {
+                            Some(prev) => region.constrain_equal(i_in.cell(), prev.cell())?,
+                            None => region.constrain_constant(i_in.cell(), Fp::ZERO)?,
+                        }
+                    }
+                    let i_out = region.assign_advice(|| "index out", fib.active_index.index[1], 0, || self.value(n, I_OUT))?;
+                    let c = region.assign_advice(|| "f_n", fib.output, 0, || self.value(n, C))?;
+                    Ok((inputs, i_out, c))
+                }
+            )?;
+            window = [inputs[1].clone(), c.clone()];
+            index = Some(i_out);
+            output = Some(c);
+        }
+        let (index, output) = (index.ok_or(Error::Synthesis)?, output.ok_or(Error::Synthesis)?);
+
+        let r_cell = poseidon_chip.load_private(layouter.namespace(|| "blinding"), Value::known(self.trace.r))?;
+        let digest = poseidon_chip.hash(layouter.namespace(|| "commitment"), [index.clone(), output.clone(), r_cell])?;
+        let public = match self.visibility {
+            Visibility::PublicK => vec![&index],
+            Visibility::PublicZ => vec![&x, &y, &output],
+            Visibility::PublicSeeds => vec![&x, &y],
+            Visibility::AllPrivateCommitted => vec![],
+        };
+        for (row, cell) in public.into_iter().chain([&digest]).enumerate() {
+            layouter.constrain_instance(cell.cell(), config.instance, row)?;
+        }
+        Ok(())
+    }
+}
+
+//the failures MockProver finds for the forged trace claiming the given instances
+fn failures(trace: Trace, visibility: Visibility, instances: Vec<Fp>) -> Vec<VerifyFailure>{
+    verify(&TamperingFibCircuit::honest(trace, visibility), instances)
+}
+
+fn verify(circ: &TamperingFibCircuit, instances: Vec<Fp>) -> Vec<VerifyFailure>{
+    match MockProver::run(8, circ, vec![instances]).unwrap().verify() {
+        Ok(()) => vec![],
+        Err(failures) => failures.into_iter().map(shape).collect(),
+    }
+}
+
+//the failure without the cell values, those are just the forged witness
+fn shape(failure: VerifyFailure) -> VerifyFailure{
+    match failure {
+        VerifyFailure::ConstraintNotSatisfied{ constraint, location, .. } => VerifyFailure::ConstraintNotSatisfied{
+            constraint,
+            location,
+            cell_values: vec![],
+        },
+        failure => failure,
+    }
+}
+
+//regions are numbered in layout order: the seed row, then row n
+fn region(n: usize) -> FailureLocation{
+    let name = if n == 0 { "seeds" } else { "row" };
+    FailureLocation::InRegion{ region: (n, name).into(), offset: 0 }
+}
+
+//gates in the order FibChip::configure creates them
+fn gate(gate: (usize, &'static str), constraint: usize, n: usize) -> VerifyFailure{
+    VerifyFailure::ConstraintNotSatisfied{
+        constraint: (gate.into(), constraint, "").into(),
+        location: region(n),
+        cell_values: vec![],
+    }
+}
+
+const ADD: (usize, &str) = (0, "add");
+const SEED: (usize, &str) = (1, "seed");
+const ACTIVE: (usize, &str) = (2, "active");
+
+//advice columns in the order FibCircuit::configure creates them
+const COL_A: (Any, usize) = (Any::Advice, 0);
+const COL_B: (Any, usize) = (Any::Advice, 1);
+const COL_C: (Any, usize) = (Any::Advice, 2);
+const INSTANCE: (Any, usize) = (Any::Instance, 0);
+
+fn permutation(column: (Any, usize), location: FailureLocation) -> VerifyFailure{
+    VerifyFailure::Permutation{ column: column.into(), location }
+}
+
+#[test]
+fn test_same_layout(){
+    let params: Params<EqAffine> = Params::new(8);
+    for visibility in [Visibility::PublicZ, Visibility::PublicK] {
+        let real = FibCircuit::<Fp>{
+            max_k: 9,
+            visibility,
+            ..FibCircuit::default()
+        };
+        let forged = TamperingFibCircuit::honest(Trace::honest(1, 1, 5, 9), visibility);
+        assert_eq!(
+            format!("{:?}", keygen_vk(&params, &real).unwrap().pinned()),
+            format!("{:?}", keygen_vk(&params, &forged).unwrap().pinned()),
+        );
+    }
+
+    //and an honest trace is accepted
+    let trace = Trace::honest(1, 2, 5, 9);
+    assert_eq!(failures(trace.clone(), Visibility::PublicZ, trace.instances(Visibility::PublicZ)), vec![]);
+}
+
+#[test]
+fn test_wrong_z(){
+    //the last row outputs f(7) + 1 instead of passing f(7) through, the commitment follows it
+    let mut trace = Trace::honest(1, 1, 7, 9);
+    trace.row(9)[C] += Fp::ONE;
+    let instances = trace.instances(Visibility::PublicZ);
+    assert_eq!(failures(trace, Visibility::PublicZ, instances), vec![gate(ADD, 0, 9)]);
+}
+
+#[test]
+fn test_wrong_seed(){
+    //an honest run from 1, 1 claimed for the seeds 2, 1
+    let trace = Trace::honest(1, 1, 7, 9);
+    let mut instances = trace.instances(Visibility::PublicZ);
+    instances[0] = Fp::from(2);
+    assert_eq!(failures(trace, Visibility::PublicZ, instances), vec![
+        permutation(INSTANCE, FailureLocation::OutsideRegion{ row: 0 }),
+        permutation(COL_A, region(0)),
+    ]);
+
+    //or f(0) itself replaced in the seed row, which no longer runs backwards to f(-1)
+    let mut trace = Trace::honest(1, 1, 7, 9);
+    trace.seeds[0] = Fp::from(2);
+    trace.rerun(1);
+    let instances = trace.instances(Visibility::PublicZ);
+    assert_eq!(failures(trace, Visibility::PublicZ, instances), vec![gate(SEED, 0, 0)]);
+}
+
+#[test]
+fn test_broken_chaining(){
+    //restart the sequence at f(6) with a = 4 instead of f(4) = 5, everything after it is consistent
+    let mut trace = Trace::honest(1, 1, 9, 9);
+    trace.row(6)[A] = Fp::from(4);
+    trace.rerun(6);
+    let instances = trace.instances(Visibility::PublicZ);
+    assert_eq!(failures(trace, Visibility::PublicZ, instances), vec![
+        permutation(COL_A, region(6)),
+        permutation(COL_B, region(5)),
+    ]);
+}
+
+#[test]
+fn test_off_by_one_k(){
+    //claim k = 8 for f(7): row 8 is marked active but still passes f(7) through
+    let mut trace = Trace::honest(1, 1, 7, 9);
+    let c = trace.row(8)[C];
+    trace.row(8)[T] = Fp::ONE;
+    trace.rerun(8);
+    trace.row(8)[C] = c;
+    trace.rerun(9);
+    let instances = trace.instances(Visibility::PublicK);
+    assert_eq!(instances[0], Fp::from(8));
+    assert_eq!(failures(trace, Visibility::PublicK, instances), vec![gate(ADD, 0, 8)]);
+
+    //claim k = 6 for f(7): row 7 computes f(7) but is marked inactive
+    let mut trace = Trace::honest(1, 1, 7, 9);
+    let c = trace.row(7)[C];
+    trace.row(7)[T] = Fp::ZERO;
+    trace.rerun(7);
+    trace.row(7)[C] = c;
+    trace.rerun(8);
+    let instances = trace.instances(Visibility::PublicK);
+    assert_eq!(instances[0], Fp::from(6));
+    assert_eq!(failures(trace, Visibility::PublicK, instances), vec![gate(ADD, 0, 7)]);
+}
+
+#[test]
+fn test_k_past_max_k(){
+    /*
+    @note
+    •   There is no lookup on k: i_in starts at 0 and each of the max_k rows adds one
+        bit, so claiming k = max_k + 1 means an i_out that doesn't count its row.
+    */
+    let mut trace = Trace::honest(1, 1, 9, 9);
+    trace.row(9)[I_OUT] += Fp::ONE;
+    let instances = trace.instances(Visibility::PublicK);
+    assert_eq!(instances[0], Fp::from(10));
+    assert_eq!(failures(trace, Visibility::PublicK, instances), vec![gate(ACTIVE, 1, 9)]);
+}
+
+#[test]
+fn test_wraparound_z(){
+    /*
+    @note
+    •   f(94) for 1, 1 doesn't fit a u64. A prover computing z natively with wrapping
+        u64 arithmetic claims f(94) mod 2^64, which is a different field element than
+        f(94) mod p: the output and the commitment no longer match the claim.
+    */
+    let k = 94;
+    let trace = Trace::honest(1, 1, k, k);
+    let wrapped = (0..k).fold((1u64, 1u64), |(a, b), _| (b, a.wrapping_add(b))).0;
+    let mut instances = trace.instances(Visibility::PublicZ);
+    assert_ne!(instances[2], Fp::from(wrapped));
+    instances[2] = Fp::from(wrapped);
+    instances[3] = poseidon::hash([Fp::from(k as u64), Fp::from(wrapped), trace.r]);
+    //z is copied into the hash input (the first region after the rows is the blinding)
+    let hash_input = FailureLocation::InRegion{ region: (k + 2, "poseidon").into(), offset: 130 };
+    assert_eq!(failures(trace, Visibility::PublicZ, instances), vec![
+        permutation(INSTANCE, FailureLocation::OutsideRegion{ row: 2 }),
+        permutation(INSTANCE, FailureLocation::OutsideRegion{ row: 3 }),
+        permutation(COL_A, hash_input),
+        permutation(COL_C, region(k)),
+    ]);
+}
+
+const MAX_K: usize = 4;
+
+//every cell of the layout, (row, column)
+fn cells() -> impl Iterator<Item = (usize, usize)>{
+    (0..=MAX_K).flat_map(|n| {
+        let columns = if n == 0 { C } else { I_OUT };
+        (A..=columns).map(move |column| (n, column))
+    })
+}
+
+//every selector and copy constraint between rows
+fn layout_tampers() -> impl Iterator<Item = Tamper>{
+    let drops = (0..=MAX_K).map(|row| Tamper::DropSelector{ row });
+    let skips = (1..=MAX_K).flat_map(|row| [A, B, I_IN].map(|column| Tamper::SkipCopy{ row, column }));
+    drops.chain(skips)
+}
+
+/*
+@note
+•   Changing any single cell of an honest layout is caught, whatever the claim is
+    changed to along with it. An input that isn't chained to the previous row or a
+    cell no gate looks at would show up here.
+*/
+#[test]
+fn test_cell_sweep(){
+    //k = 2 has both active and inactive rows
+    let trace = Trace::honest(1, 1, 2, MAX_K);
+    for visibility in [Visibility::PublicZ, Visibility::AllPrivateCommitted] {
+        for (row, column) in cells() {
+            let mut forged = trace.clone();
+            let value = trace.at(row, column) + Fp::ONE;
+            let circ = TamperingFibCircuit{
+                trace: trace.clone(),
+                visibility,
+                tampers: vec![Tamper::Cell{ row, column, value }],
+            };
+            *forged.cell(row, column) = value;
+            let instances = forged.instances(visibility);
+            assert!(!verify(&circ, instances).is_empty(), "cell ({}, {}) {:?}", row, column, visibility);
+        }
+    }
+}
+
+/*
+@note
+•   A selector or copy constraint missing from the layout is a different circuit: its
+    verifying key isn't FibCircuit's, so proofs for it don't verify against the real key.
+•   Each of them is also load-bearing: the forgery it would let through is accepted
+    by the tampered layout and rejected by the real one. The step column pins i_in
+    on active rows only, so an index copy is what keeps an inactive row from
+    counting past k, and an active row from skipping its term while still counting.
+    Those forgeries are about k and claimed under PublicK.
+*/
+#[test]
+fn test_layout_sweep(){
+    let params: Params<EqAffine> = Params::new(8);
+    let pinned = |circ: &TamperingFibCircuit| format!("{:?}", keygen_vk(&params, circ).unwrap().pinned());
+    let trace = Trace::honest(1, 1, 2, MAX_K);
+    let real = pinned(&TamperingFibCircuit::honest(trace.clone(), Visibility::PublicZ));
+
+    for tamper in layout_tampers() {
+        let circ = TamperingFibCircuit{
+            trace: trace.clone(),
+            visibility: Visibility::PublicZ,
+            tampers: vec![tamper],
+        };
+        assert_ne!(pinned(&circ), real, "{:?}", tamper);
+
+        let mut forged = trace.clone();
+        match tamper {
+            //f(-1) off by one, and row 1 starting from it
+            Tamper::DropSelector{ row: 0 } => {
+                forged.seeds[C] += Fp::ONE;
+                forged.row(1)[A] += Fp::ONE;
+                forged.rerun(1);
+            }
+            //the row's output off by one, and the rows after it following it
+            Tamper::DropSelector{ row } => {
+                forged.row(row)[C] += Fp::ONE;
+                if row < MAX_K {
+                    forged.rerun(row + 1);
+                }
+            }
+            Tamper::SkipCopy{ row, column: A } => {
+                forged.row(row)[A] += Fp::ONE;
+                forged.rerun(row);
+            }
+            Tamper::SkipCopy{ row, column: B } => {
+                forged.row(row)[B] += Fp::ONE;
+                forged.recompute(row);
+                if row < MAX_K {
+                    forged.row(row + 1)[A] = forged.row(row)[B];
+                    forged.rerun(row + 1);
+                }
+            }
+            //an inactive row picking up the index at max_k, claiming k = max_k
+            Tamper::SkipCopy{ row, column: I_IN } if forged.at(row, T) == Fp::ZERO => {
+                forged.row(row)[I_IN] = Fp::from(MAX_K as u64);
+                forged.recompute(row);
+                if row < MAX_K {
+                    forged.rerun(row + 1);
+                }
+            }
+            //an active row passing its input through, the index one ahead so k stays the same
+            Tamper::SkipCopy{ row, column: I_IN } => {
+                forged.row(row)[T] = Fp::ZERO;
+                forged.row(row)[I_IN] += Fp::ONE;
+                forged.recompute(row);
+                if row < MAX_K {
+                    forged.rerun(row + 1);
+                }
+            }
+            _ => unreachable!("{:?}", tamper),
+        }
+        let visibility = match tamper {
+            Tamper::SkipCopy{ column: I_IN, .. } => Visibility::PublicK,
+            _ => Visibility::PublicZ,
+        };
+        let instances = forged.instances(visibility);
+        let honest = TamperingFibCircuit::honest(forged.clone(), visibility);
+        let tampered = TamperingFibCircuit{
+            trace: forged,
+            visibility,
+            tampers: vec![tamper],
+        };
+        assert_eq!(verify(&tampered, instances.clone()), vec![], "{:?}", tamper);
+        let rejected = verify(&honest, instances);
+        assert!(!rejected.is_empty(), "{:?}", tamper);
+        //only the missing copy catches those
+        if let Tamper::SkipCopy{ column: I_IN, .. } = tamper {
+            assert!(rejected.iter().all(|failure| matches!(failure, VerifyFailure::Permutation{ .. })), "{:?}", tamper);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use halo2_proofs::plonk::Error;
+use std::fmt;
+
+use crate::vk::Fingerprint;
+
+/*
+@note
+•   Everything that can go wrong between building a FibCircuit and checking its proof.
+*/
+#[derive(Debug)]
+pub enum FibError{
+    //k is past the last row the circuit lays out
+    InvalidIndex{ k: usize, max_k: usize },
+    //the circuit has no row to hold f(k)
+    NotEnoughRows{ max_k: usize },
+    //the claimed z is not f(k)
+    WrongOutput{ k: usize },
+    //the builder was not given a required part of the claim
+    MissingInput(&'static str),
+    //halo2 failed while laying out, keying or proving the circuit
+    Synthesis(Error),
+    //bytes that should hold a proof or key could not be read or written
+    Serialization(String),
+    //reading or writing a file failed
+    Io(std::io::Error),
+    //the public inputs don't fit the circuit: wrong number of instance columns or too many values
+    InvalidInstances,
+    //the proof does not verify against the key and public inputs
+    InvalidProof,
+    //the proof at this position of a batch does not verify (see prover::verify_batch)
+    InvalidBatchItem{ index: usize },
+    //a verifying key is not the one that was expected (see vk::fingerprint)
+    KeyMismatch{ expected: Fingerprint, found: Fingerprint },
+    //a proof header names another configuration than the verifier expects (see Proof::check_key)
+    ConfigMismatch{ field: &'static str, expected: u64, found: u64 },
+}
+
+impl fmt::Display for FibError{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        match self {
+            FibError::InvalidIndex{ k, max_k } => write!(f, "index {} is larger than max_k = {}", k, max_k),
+            FibError::NotEnoughRows{ max_k } => write!(f, "max_k = {} leaves no row for f(k)", max_k),
+            FibError::WrongOutput{ k } => write!(f, "claimed output is not f({})", k),
+            FibError::MissingInput(input) => write!(f, "missing {}", input),
+            FibError::Synthesis(e) => write!(f, "synthesis failed: {}", e),
+            FibError::Serialization(e) => write!(f, "serialization failed: {}", e),
+            FibError::Io(e) => write!(f, "i/o error: {}", e),
+            FibError::InvalidInstances => write!(f, "public inputs do not match the circuit"),
+            FibError::InvalidProof => write!(f, "proof is invalid"),
+            FibError::InvalidBatchItem{ index } => write!(f, "proof {} of the batch is invalid", index),
+            FibError::KeyMismatch{ expected, found } => write!(
+                f,
+                "verifying key mismatch: expected fingerprint {}, found {}",
+                hex(expected),
+                hex(found),
+            ),
+            FibError::ConfigMismatch{ field, expected, found } => write!(
+                f,
+                "proof is for {} = {}, expected {}",
+                field,
+                found,
+                expected,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FibError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)>{
+        match self {
+            FibError::Synthesis(e) => Some(e),
+            FibError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for FibError{
+    fn from(e: Error) -> Self{
+        FibError::Synthesis(e)
+    }
+}
+
+impl From<std::io::Error> for FibError{
+    fn from(e: std::io::Error) -> Self{
+        FibError::Io(e)
+    }
+}
+
+//lowercase, how fingerprints and checksums are printed
+pub fn hex(bytes: &[u8]) -> String{
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,241 @@
+use blake2b_simd::Params as Blake2bParams;
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::{keygen_pk, keygen_vk, ProvingKey, VerifyingKey},
+    poly::commitment::Params,
+};
+use std::{cell::RefCell, collections::HashMap, fs, path::{Path, PathBuf}};
+
+use crate::circuits::circuit_naive::{FibCircuit, Visibility};
+use crate::error::FibError;
+use crate::proof::Layout;
+use crate::vk::{domain_k, export_vk, ExportedVk};
+
+/*
+@note
+•   A cache directory for IPA params, so they aren't generated from scratch on every
+    run, and the key files setup hands out.
+•   Every file is a Blake2b-256 checksum of its contents followed by the contents.
+    A params file that is missing or fails the checksum is regenerated and written again.
+•   Params are indexed by the domain size (params-k8.bin) and key files by
+    (layout, max_k, domain size) plus the visibility (naive-m16-k8-v2.vk).
+•   halo2_proofs 0.3 can write Params but not keys, so keys can't be cached on disk.
+    They are kept in memory per (layout, max_k, domain size, visibility) instead, so
+    keygen_pk runs once per configuration and process, and every run of the command
+    line tool still pays for it.
+•   A key file is not a cache: it is the exported descriptor of the verifying key (see
+    vk::ExportedVk), i.e. the configuration the key was made for and its fingerprint.
+    Whoever verifies can pin the key with it, vk::import_vk rebuilds it and refuses
+    a key that doesn't match.
+*/
+pub struct KeyStore{
+    dir: PathBuf,
+    keys: RefCell<HashMap<KeyId, ProvingKey<EqAffine>>>,
+}
+
+//layout, max_k, domain size, visibility
+type KeyId = (Layout, usize, u32, Visibility);
+
+impl KeyStore{
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, FibError>{
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self{ dir, keys: RefCell::default() })
+    }
+
+    pub fn params_path(&self, domain_k: u32) -> PathBuf{
+        self.dir.join(format!("params-k{}.bin", domain_k))
+    }
+
+    pub fn key_path(&self, layout: Layout, max_k: usize, domain_k: u32, visibility: Visibility) -> PathBuf{
+        let layout = match layout {
+            Layout::Naive => "naive",
+            Layout::Rotation => "rotation",
+            Layout::Doubling => "doubling",
+        };
+        self.dir.join(format!("{}-m{}-k{}-v{}.vk", layout, max_k, domain_k, visibility.id()))
+    }
+
+    //Params::new(domain_k), from the cache if it's there and intact
+    pub fn params(&self, domain_k: u32) -> Result<Params<EqAffine>, FibError>{
+        let path = self.params_path(domain_k);
+        if let Some(bytes) = self.read(&path)? {
+            if let Ok(params) = Params::read(&mut bytes.as_slice()) {
+                return Ok(params);
+            }
+        }
+        let params: Params<EqAffine> = Params::new(domain_k);
+        let mut bytes = vec![];
+        params.write(&mut bytes)?;
+        self.write(&path, &bytes)?;
+        Ok(params)
+    }
+
+    /*
+    @note
+    •   The proving key for FibCircuit with the given max_k and visibility over params,
+        generated on the first call for the configuration and cloned from memory after.
+    */
+    pub fn proving_key(
+        &self,
+        params: &Params<EqAffine>,
+        max_k: usize,
+        visibility: Visibility,
+    ) -> Result<ProvingKey<EqAffine>, FibError>{
+        let id = (Layout::Naive, max_k, params_k(params), visibility);
+        if let Some(pk) = self.keys.borrow().get(&id) {
+            return Ok(pk.clone());
+        }
+        let circuit = circuit(max_k, visibility);
+        let pk = keygen_pk(params, keygen_vk(params, &circuit)?, &circuit)?;
+        self.keys.borrow_mut().insert(id, pk.clone());
+        Ok(pk)
+    }
+
+    //same as proving_key, for when only the verifying key is needed: keygen_pk is skipped if it hasn't run yet
+    pub fn verifying_key(
+        &self,
+        params: &Params<EqAffine>,
+        max_k: usize,
+        visibility: Visibility,
+    ) -> Result<VerifyingKey<EqAffine>, FibError>{
+        let id = (Layout::Naive, max_k, params_k(params), visibility);
+        if let Some(pk) = self.keys.borrow().get(&id) {
+            return Ok(pk.get_vk().clone());
+        }
+        Ok(keygen_vk(params, &circuit(max_k, visibility))?)
+    }
+
+    //writes the key file for vk, made for FibCircuit with max_k and visibility, and returns its path
+    pub fn export_key(&self, vk: &VerifyingKey<EqAffine>, max_k: usize, visibility: Visibility) -> Result<PathBuf, FibError>{
+        let path = self.key_path(Layout::Naive, max_k, domain_k(vk), visibility);
+        self.write(&path, &export_vk(vk, max_k, visibility).to_bytes())?;
+        Ok(path)
+    }
+
+    //reads a key file written by export_key, which can be anywhere
+    pub fn read_key(&self, path: &Path) -> Result<ExportedVk, FibError>{
+        match self.read(path)? {
+            Some(bytes) => ExportedVk::from_bytes(&bytes),
+            None => Err(FibError::Io(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} is missing or fails its checksum", path.display()),
+            ))),
+        }
+    }
+
+    //the contents of path, None if it doesn't exist or fails the checksum
+    fn read(&self, path: &Path) -> Result<Option<Vec<u8>>, FibError>{
+        let bytes = match fs::read(path) {
+            Ok(bytes) => bytes,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        if bytes.len() < 32 || bytes[..32] != checksum(&bytes[32..]) {
+            return Ok(None);
+        }
+        Ok(Some(bytes[32..].to_vec()))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> Result<(), FibError>{
+        let mut bytes = checksum(contents).to_vec();
+        bytes.extend(contents);
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+}
+
+//the domain size params were made for, Params has no accessor for it
+fn params_k(params: &Params<EqAffine>) -> u32{
+    params.get_g().len().trailing_zeros()
+}
+
+//the witness-less circuit the keys are generated from
+fn circuit(max_k: usize, visibility: Visibility) -> FibCircuit<Fp>{
+    FibCircuit{
+        max_k,
+        visibility,
+        ..FibCircuit::default()
+    }
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 32]{
+    let hash = Blake2bParams::new()
+        .hash_length(32)
+        .personal(b"FibCircuit-cache")
+        .hash(bytes);
+    let mut checksum = [0; 32];
+    checksum.copy_from_slice(hash.as_bytes());
+    checksum
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use crate::test_util::TempDir;
+    use crate::vk::fingerprint;
+
+    #[test]
+    fn test_params_cached(){
+        let dir = TempDir::new("params");
+        let store = KeyStore::new(dir.dir()).unwrap();
+        let path = store.params_path(6);
+
+        store.params(6).unwrap();
+        let written = fs::read(&path).unwrap();
+        store.params(6).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), written);
+
+        //a corrupted file fails the checksum and gets regenerated
+        let mut corrupted = written.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        fs::write(&path, corrupted).unwrap();
+        let mut bytes = vec![];
+        store.params(6).unwrap().write(&mut bytes).unwrap();
+        assert_eq!(bytes, written[32..]);
+        assert_eq!(fs::read(&path).unwrap(), written);
+    }
+
+    #[test]
+    fn test_keys_in_memory(){
+        let dir = TempDir::new("keys");
+        let store = KeyStore::new(dir.dir()).unwrap();
+        let params = store.params(8).unwrap();
+
+        let pk = store.proving_key(&params, 16, Visibility::PublicZ).unwrap();
+        assert_eq!(store.keys.borrow().len(), 1);
+        let again = store.proving_key(&params, 16, Visibility::PublicZ).unwrap();
+        assert_eq!(fingerprint(again.get_vk()), fingerprint(pk.get_vk()));
+        assert_eq!(fingerprint(&store.verifying_key(&params, 16, Visibility::PublicZ).unwrap()), fingerprint(pk.get_vk()));
+        assert_eq!(store.keys.borrow().len(), 1);
+
+        //other configurations get their own key, the domain comes from params
+        store.proving_key(&params, 12, Visibility::PublicZ).unwrap();
+        store.proving_key(&params, 16, Visibility::PublicK).unwrap();
+        store.proving_key(&store.params(9).unwrap(), 16, Visibility::PublicZ).unwrap();
+        assert_eq!(store.keys.borrow().len(), 4);
+        assert!(store.keys.borrow().contains_key(&(Layout::Naive, 16, 9, Visibility::PublicZ)));
+    }
+
+    #[test]
+    fn test_key_file(){
+        let dir = TempDir::new("key-file");
+        let store = KeyStore::new(dir.dir()).unwrap();
+        let params = store.params(9).unwrap();
+        let vk = store.verifying_key(&params, 16, Visibility::PublicSeeds).unwrap();
+
+        //named after the domain of the key itself
+        let path = store.export_key(&vk, 16, Visibility::PublicSeeds).unwrap();
+        assert_eq!(path, store.key_path(Layout::Naive, 16, 9, Visibility::PublicSeeds));
+        let exported = store.read_key(&path).unwrap();
+        assert_eq!(exported, export_vk(&vk, 16, Visibility::PublicSeeds));
+        assert_eq!(exported.domain_k, 9);
+
+        let mut corrupted = fs::read(&path).unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        fs::write(&path, corrupted).unwrap();
+        assert!(store.read_key(&path).is_err());
+    }
+}
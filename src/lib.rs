@@ -1,3 +1,20 @@
 pub mod circuits {
+    pub mod circuit_doubling;
+    pub mod circuit_multi;
     pub mod circuit_naive;
+    pub mod circuit_packed;
+    pub mod circuit_rotation;
+    pub mod linear_recurrence;
+    pub mod poseidon;
+    #[cfg(test)]
+    mod soundness;
 }
+pub mod error;
+pub mod keystore;
+pub mod proof;
+pub mod prover;
+pub mod statement;
+#[doc(hidden)]
+pub mod test_util;
+pub mod vk;
+pub mod witness;
@@ -0,0 +1,345 @@
+use halo2_proofs::{
+    pasta::{group::ff::PrimeField, EqAffine, Fp},
+    plonk::VerifyingKey,
+};
+
+use crate::error::FibError;
+use crate::vk::{ExportedVk, Fingerprint};
+
+/*
+@note
+•   A proof as it gets stored or shipped: the raw halo2 proof plus everything needed
+    to check it against the right key. All integers are little endian.
+
+    magic           4 bytes     "FIBP"
+    version         u16         VERSION
+    curve           u8          Curve id
+    layout          u8          Layout id
+    max_k           u64
+    domain k        u32         the circuit has 2^k rows (Params::new(k))
+    vk fingerprint  32 bytes    (version 2 only) see vk::fingerprint
+    columns         u32         number of instance columns, then for each one
+      len           u32         number of values, then
+      values        32 bytes    each, canonical little endian encoding
+    proof len       u32
+    proof           bytes
+
+•   Parsing is strict: wrong magic, an unknown version/curve/layout, non-canonical
+    field elements, truncated input and trailing bytes are all errors.
+•   Version 1 files (without the fingerprint) are still read, and written back as
+    version 1 so the bytes don't change.
+*/
+pub const MAGIC: [u8; 4] = *b"FIBP";
+pub const VERSION: u16 = 2;
+
+//circuit field and commitment curve
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Curve{
+    //circuit over Fp, IPA commitments over EqAffine (Vesta)
+    PastaFp,
+}
+
+//which circuit the proof is for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Layout{
+    Naive,
+    Rotation,
+    Doubling,
+}
+
+impl Curve{
+    pub fn id(self) -> u8{
+        match self {
+            Curve::PastaFp => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, FibError>{
+        match id {
+            1 => Ok(Curve::PastaFp),
+            _ => Err(FibError::Serialization(format!("unknown curve id {}", id))),
+        }
+    }
+}
+
+impl Layout{
+    pub fn id(self) -> u8{
+        match self {
+            Layout::Naive => 1,
+            Layout::Rotation => 2,
+            Layout::Doubling => 3,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Result<Self, FibError>{
+        match id {
+            1 => Ok(Layout::Naive),
+            2 => Ok(Layout::Rotation),
+            3 => Ok(Layout::Doubling),
+            _ => Err(FibError::Serialization(format!("unknown layout id {}", id))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Proof{
+    pub curve: Curve,
+    pub layout: Layout,
+    pub max_k: u64,
+    pub domain_k: u32,
+    //fingerprint of the key the proof was made with, None for version 1 files
+    pub vk_fingerprint: Option<Fingerprint>,
+    //one Vec per instance column
+    pub instances: Vec<Vec<Fp>>,
+    pub proof: Vec<u8>,
+}
+
+impl Proof{
+    /*
+    @note
+    •   Rejects the proof if it was made with a different key than vk. There is nothing
+        to compare for version 1 files.
+    */
+    pub fn check_vk(&self, vk: &VerifyingKey<EqAffine>) -> Result<(), FibError>{
+        match self.vk_fingerprint {
+            Some(expected) => crate::vk::check_fingerprint(vk, expected),
+            None => Ok(()),
+        }
+    }
+
+    /*
+    @note
+    •   Rejects the proof unless it was made for the configuration the verifier expects:
+        the same curve, layout, max_k and domain, and for version 2 files the same key.
+        The header is whatever the prover wrote, so it is only compared against, never
+        used to pick the key.
+    */
+    pub fn check_key(&self, expected: &ExportedVk) -> Result<(), FibError>{
+        let fields = [
+            ("curve", expected.curve.id() as u64, self.curve.id() as u64),
+            ("layout", expected.layout.id() as u64, self.layout.id() as u64),
+            ("max_k", expected.max_k, self.max_k),
+            ("domain k", expected.domain_k as u64, self.domain_k as u64),
+        ];
+        for (field, expected, found) in fields {
+            if expected != found {
+                return Err(FibError::ConfigMismatch{ field, expected, found });
+            }
+        }
+        match self.vk_fingerprint {
+            Some(found) if found != expected.fingerprint => Err(FibError::KeyMismatch{
+                expected: expected.fingerprint,
+                found,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    //the instances in the shape prover::verify takes them
+    pub fn instance_slices(&self) -> Vec<&[Fp]>{
+        self.instances.iter().map(|column| column.as_slice()).collect()
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, FibError>{
+        let mut bytes = vec![];
+        let version: u16 = if self.vk_fingerprint.is_some() { VERSION } else { 1 };
+        bytes.extend(MAGIC);
+        bytes.extend(version.to_le_bytes());
+        bytes.push(self.curve.id());
+        bytes.push(self.layout.id());
+        bytes.extend(self.max_k.to_le_bytes());
+        bytes.extend(self.domain_k.to_le_bytes());
+        if let Some(fingerprint) = self.vk_fingerprint {
+            bytes.extend(fingerprint);
+        }
+        bytes.extend(len_u32(self.instances.len())?.to_le_bytes());
+        for column in &self.instances {
+            bytes.extend(len_u32(column.len())?.to_le_bytes());
+            for value in column {
+                bytes.extend(value.to_repr());
+            }
+        }
+        bytes.extend(len_u32(self.proof.len())?.to_le_bytes());
+        bytes.extend(&self.proof);
+        Ok(bytes)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FibError>{
+        let mut reader = Reader{ bytes };
+        if reader.take(4)? != MAGIC {
+            return Err(FibError::Serialization("not a proof file (bad magic)".into()));
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != 1 && version != VERSION {
+            return Err(FibError::Serialization(format!("unsupported version {}", version)));
+        }
+        let curve = Curve::from_id(reader.array::<1>()?[0])?;
+        let layout = Layout::from_id(reader.array::<1>()?[0])?;
+        let max_k = u64::from_le_bytes(reader.array()?);
+        let domain_k = u32::from_le_bytes(reader.array()?);
+        let vk_fingerprint = if version == 1 { None } else { Some(reader.array()?) };
+
+        let columns = reader.len()?;
+        let mut instances = Vec::new();
+        for _ in 0..columns {
+            let len = reader.len()?;
+            let mut column = Vec::new();
+            for _ in 0..len {
+                let value: Option<Fp> = Fp::from_repr(reader.array()?).into();
+                column.push(value.ok_or_else(|| FibError::Serialization("non-canonical field element".into()))?);
+            }
+            instances.push(column);
+        }
+
+        let len = reader.len()?;
+        let proof = reader.take(len)?.to_vec();
+        if !reader.bytes.is_empty() {
+            return Err(FibError::Serialization(format!("{} trailing bytes", reader.bytes.len())));
+        }
+
+        Ok(Proof{
+            curve,
+            layout,
+            max_k,
+            domain_k,
+            vk_fingerprint,
+            instances,
+            proof,
+        })
+    }
+}
+
+fn len_u32(len: usize) -> Result<u32, FibError>{
+    u32::try_from(len).map_err(|_| FibError::Serialization(format!("length {} does not fit in u32", len)))
+}
+
+//reads an envelope front to back, every read checks there are enough bytes left
+pub(crate) struct Reader<'a>{
+    pub(crate) bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a>{
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], FibError>{
+        if self.bytes.len() < n {
+            return Err(FibError::Serialization("truncated input".into()));
+        }
+        let (head, tail) = self.bytes.split_at(n);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const L: usize>(&mut self) -> Result<[u8; L], FibError>{
+        let mut array = [0; L];
+        array.copy_from_slice(self.take(L)?);
+        Ok(array)
+    }
+
+    fn len(&mut self) -> Result<usize, FibError>{
+        Ok(u32::from_le_bytes(self.array()?) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    //written by to_bytes when VERSION was 1 and 2, later versions still have to read them
+    const GOLDEN: &[u8] = include_bytes!("../tests/data/proof_v1.bin");
+    const GOLDEN_V2: &[u8] = include_bytes!("../tests/data/proof_v2.bin");
+
+    fn golden() -> Proof{
+        Proof{
+            curve: Curve::PastaFp,
+            layout: Layout::Naive,
+            max_k: 16,
+            domain_k: 8,
+            vk_fingerprint: None,
+            instances: vec![vec![Fp::from(1), Fp::from(1), Fp::from(89), -Fp::from(1)]],
+            proof: (0..=255).collect(),
+        }
+    }
+
+    #[test]
+    fn test_golden(){
+        assert_eq!(golden().to_bytes().unwrap(), GOLDEN);
+        assert_eq!(Proof::from_bytes(GOLDEN).unwrap(), golden());
+
+        let golden_v2 = Proof{
+            vk_fingerprint: Some(std::array::from_fn(|i| i as u8)),
+            ..golden()
+        };
+        assert_eq!(golden_v2.to_bytes().unwrap(), GOLDEN_V2);
+        assert_eq!(Proof::from_bytes(GOLDEN_V2).unwrap(), golden_v2);
+    }
+
+    #[test]
+    fn test_strict(){
+        let reject = |bytes: &[u8], message: &str| match Proof::from_bytes(bytes) {
+            Err(FibError::Serialization(e)) => assert!(e.contains(message), "{}", e),
+            other => panic!("expected a serialization error, got {:?}", other),
+        };
+
+        let mut bytes = GOLDEN.to_vec();
+        bytes[0] = b'X';
+        reject(&bytes, "magic");
+
+        let mut bytes = GOLDEN.to_vec();
+        bytes[4] = 3;
+        reject(&bytes, "version 3");
+
+        let mut bytes = GOLDEN.to_vec();
+        bytes[6] = 9;
+        reject(&bytes, "curve id 9");
+
+        let mut bytes = GOLDEN.to_vec();
+        bytes[7] = 0;
+        reject(&bytes, "layout id 0");
+
+        //the last instance is p - 1, turn it into p
+        let mut bytes = GOLDEN.to_vec();
+        let last = 4 + 2 + 1 + 1 + 8 + 4 + 4 + 4 + 3*32;
+        bytes[last] += 1;
+        reject(&bytes, "non-canonical");
+
+        reject(&GOLDEN[..GOLDEN.len() - 1], "truncated");
+        reject(&GOLDEN_V2[..30], "truncated");
+        reject(&[GOLDEN, &[0]].concat(), "1 trailing bytes");
+    }
+
+    #[test]
+    fn test_check_key(){
+        let expected = ExportedVk{
+            curve: Curve::PastaFp,
+            layout: Layout::Naive,
+            visibility: crate::circuits::circuit_naive::Visibility::PublicZ,
+            max_k: 16,
+            domain_k: 8,
+            fingerprint: std::array::from_fn(|i| i as u8),
+        };
+        let proof = Proof{
+            vk_fingerprint: Some(expected.fingerprint),
+            ..golden()
+        };
+        assert!(proof.check_key(&expected).is_ok());
+        //version 1 has no fingerprint, the header still has to match
+        assert!(golden().check_key(&expected).is_ok());
+
+        let mismatch = |proof: Proof| proof.check_key(&expected).unwrap_err();
+        assert!(matches!(
+            mismatch(Proof{ max_k: 12, ..proof.clone() }),
+            FibError::ConfigMismatch{ field: "max_k", expected: 16, found: 12 }
+        ));
+        assert!(matches!(
+            mismatch(Proof{ domain_k: 9, ..golden() }),
+            FibError::ConfigMismatch{ field: "domain k", expected: 8, found: 9 }
+        ));
+        assert!(matches!(
+            mismatch(Proof{ layout: Layout::Doubling, ..proof.clone() }),
+            FibError::ConfigMismatch{ field: "layout", .. }
+        ));
+        assert!(matches!(
+            mismatch(Proof{ vk_fingerprint: Some([0; 32]), ..proof }),
+            FibError::KeyMismatch{ .. }
+        ));
+    }
+}
@@ -0,0 +1,96 @@
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::{
+        create_proof, keygen_pk, keygen_vk, verify_proof,
+        BatchVerifier, Circuit, Error, ProvingKey, SingleVerifier, VerifyingKey,
+    },
+    poly::commitment::Params,
+    transcript::{Blake2bRead, Blake2bWrite, Challenge255},
+};
+use rand_core::OsRng;
+
+use crate::error::FibError;
+
+/*
+@note
+•   Real proofs over the Pasta cycle: the circuit lives over Fp (the Vesta scalar field)
+    and is committed to with IPA over EqAffine (Vesta points), so no trusted setup is needed.
+•   instances holds one slice of public values per instance column of the circuit.
+•   Keys have to be generated from a circuit with the same shape as the one being proven,
+    e.g. FibCircuit::without_witnesses() with the same max_k.
+*/
+pub fn keygen<C: Circuit<Fp>>(
+    params: &Params<EqAffine>,
+    circuit: &C,
+) -> Result<ProvingKey<EqAffine>, FibError> {
+    let vk: VerifyingKey<EqAffine> = keygen_vk(params, circuit)?;
+    let pk = keygen_pk(params, vk, circuit)?;
+    Ok(pk)
+}
+
+pub fn prove<C: Circuit<Fp>>(
+    params: &Params<EqAffine>,
+    pk: &ProvingKey<EqAffine>,
+    circuit: C,
+    instances: &[&[Fp]],
+) -> Result<Vec<u8>, FibError> {
+    let mut transcript = Blake2bWrite::<_, EqAffine, Challenge255<_>>::init(vec![]);
+    create_proof(
+        params,
+        pk,
+        &[circuit],
+        &[instances],
+        OsRng,
+        &mut transcript,
+    )?;
+    Ok(transcript.finalize())
+}
+
+pub fn verify(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proof: &[u8],
+    instances: &[&[Fp]],
+) -> Result<(), FibError> {
+    let strategy = SingleVerifier::new(params);
+    let mut transcript = Blake2bRead::<_, EqAffine, Challenge255<_>>::init(proof);
+    //a failed check and a truncated/garbled proof both just mean the proof is bad
+    match verify_proof(params, vk, strategy, &[instances], &mut transcript) {
+        Ok(()) => Ok(()),
+        Err(Error::ConstraintSystemFailure) | Err(Error::Transcript(_)) => Err(FibError::InvalidProof),
+        Err(Error::InvalidInstances) | Err(Error::InstanceTooLarge) => Err(FibError::InvalidInstances),
+        Err(e) => Err(FibError::Synthesis(e)),
+    }
+}
+
+/*
+@note
+•   Checks many proofs made with the same key at once: BatchVerifier folds every proof's
+    MSM into a single one (each scaled by a random factor) and evaluates it once, which
+    is much cheaper than one verify per proof.
+•   The batch check only says whether all of them are valid, so when it fails the proofs
+    are verified again one by one to find the first bad one.
+*/
+pub fn verify_batch(
+    params: &Params<EqAffine>,
+    vk: &VerifyingKey<EqAffine>,
+    proofs: &[(&[u8], &[&[Fp]])],
+) -> Result<(), FibError> {
+    let mut batch = BatchVerifier::new();
+    for (proof, instances) in proofs {
+        let instances = instances.iter().map(|column| column.to_vec()).collect();
+        batch.add_proof(vec![instances], proof.to_vec());
+    }
+    if batch.finalize(params, vk) {
+        return Ok(());
+    }
+    for (index, (proof, instances)) in proofs.iter().enumerate() {
+        match verify(params, vk, proof, instances) {
+            Ok(()) => {}
+            Err(FibError::InvalidProof) => return Err(FibError::InvalidBatchItem { index }),
+            Err(e) => return Err(e),
+        }
+    }
+    //every proof verifies on its own, so the batch only failed with negligible probability
+    Ok(())
+}
@@ -0,0 +1,187 @@
+use halo2_proofs::{arithmetic::Field, circuit::Value};
+use rand_core::OsRng;
+
+use crate::circuits::circuit_naive::{FibCircuit, Visibility};
+use crate::circuits::poseidon::PoseidonField;
+use crate::error::FibError;
+use crate::witness::fib;
+
+//bound used when the builder isn't given one, the layout still fits in 2^8 rows
+pub const DEFAULT_MAX_K: usize = 64;
+
+/*
+@note
+•   The claim f(0) = x, f(1) = y, f(k) = z split into what the verifier knows
+    (the statement) and what only the prover knows (the witness).
+•   max_k and the visibility are part of the statement: they are fixed by the keys.
+    Which of x, y, z actually reach the instance column depends on the visibility,
+    k and r only ever reach it through the commitment (or as k itself in PublicK).
+*/
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FibStatement<F: Field>{
+    pub x: F,
+    pub y: F,
+    pub z: F,
+    pub max_k: usize,
+    pub visibility: Visibility,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FibWitness<F: Field>{
+    pub k: usize,
+    //blinding for the commitment
+    pub r: F,
+}
+
+impl<F: PoseidonField> FibCircuit<F>{
+    /*
+    @note
+    •   Checks the claim natively before any proving work: the index has to fit the
+        layout and z has to be f(k), otherwise the proof would just fail to verify.
+    */
+    pub fn new(statement: FibStatement<F>, witness: FibWitness<F>) -> Result<Self, FibError>{
+        let circuit = FibCircuit{
+            a: Value::known(statement.x),
+            b: Value::known(statement.y),
+            k: Value::known(witness.k),
+            z: Value::known(statement.z),
+            r: Value::known(witness.r),
+            max_k: statement.max_k,
+            visibility: statement.visibility,
+        };
+        circuit.check()?;
+        if fib(statement.x, statement.y, witness.k) != statement.z {
+            return Err(FibError::WrongOutput{ k: witness.k });
+        }
+        Ok(circuit)
+    }
+
+    pub fn builder() -> FibCircuitBuilder<F>{
+        FibCircuitBuilder::default()
+    }
+}
+
+/*
+@note
+•   FibCircuit::builder().seeds(x, y).index(k).build()
+•   z is computed unless one is supplied with output(z), in which case it is checked.
+    max_k defaults to DEFAULT_MAX_K, the visibility to PublicZ and the blinding to a
+    fresh random value.
+*/
+pub struct FibCircuitBuilder<F: Field>{
+    seeds: Option<(F, F)>,
+    k: Option<usize>,
+    z: Option<F>,
+    r: Option<F>,
+    max_k: usize,
+    visibility: Visibility,
+}
+
+impl<F: Field> Default for FibCircuitBuilder<F>{
+    fn default() -> Self{
+        Self{
+            seeds: None,
+            k: None,
+            z: None,
+            r: None,
+            max_k: DEFAULT_MAX_K,
+            visibility: Visibility::default(),
+        }
+    }
+}
+
+impl<F: PoseidonField> FibCircuitBuilder<F>{
+    pub fn seeds(mut self, x: F, y: F) -> Self{
+        self.seeds = Some((x, y));
+        self
+    }
+
+    pub fn index(mut self, k: usize) -> Self{
+        self.k = Some(k);
+        self
+    }
+
+    pub fn output(mut self, z: F) -> Self{
+        self.z = Some(z);
+        self
+    }
+
+    pub fn blinding(mut self, r: F) -> Self{
+        self.r = Some(r);
+        self
+    }
+
+    pub fn max_k(mut self, max_k: usize) -> Self{
+        self.max_k = max_k;
+        self
+    }
+
+    pub fn visibility(mut self, visibility: Visibility) -> Self{
+        self.visibility = visibility;
+        self
+    }
+
+    pub fn build(self) -> Result<FibCircuit<F>, FibError>{
+        let (x, y) = self.seeds.ok_or(FibError::MissingInput("seeds"))?;
+        let k = self.k.ok_or(FibError::MissingInput("index"))?;
+        //the layout is checked before z, which costs O(k) to compute
+        FibCircuit::<F>{ k: Value::known(k), max_k: self.max_k, ..FibCircuit::default() }.check()?;
+        let statement = FibStatement{
+            x,
+            y,
+            z: self.z.unwrap_or_else(|| fib(x, y, k)),
+            max_k: self.max_k,
+            visibility: self.visibility,
+        };
+        let witness = FibWitness{
+            k,
+            r: self.r.unwrap_or_else(|| F::random(OsRng)),
+        };
+        FibCircuit::new(statement, witness)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use halo2_proofs::{dev::MockProver, pasta::Fp};
+
+    #[test]
+    fn test_builder(){
+        let circ = FibCircuit::builder()
+            .seeds(Fp::from(1), Fp::from(1))
+            .index(12)
+            .max_k(16)
+            .build()
+            .unwrap();
+        assert_eq!(circ.instances()[2], Fp::from(233));
+
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_builder_checks(){
+        let builder = || FibCircuit::<Fp>::builder().seeds(Fp::from(1), Fp::from(2)).max_k(16);
+
+        assert!(builder().index(9).output(Fp::from(89)).build().is_ok());
+        assert!(matches!(builder().index(9).output(Fp::from(90)).build(), Err(FibError::WrongOutput{ k: 9 })));
+        assert!(matches!(builder().index(17).build(), Err(FibError::InvalidIndex{ k: 17, max_k: 16 })));
+        assert!(matches!(builder().build(), Err(FibError::MissingInput("index"))));
+        assert!(matches!(FibCircuit::<Fp>::builder().index(3).build(), Err(FibError::MissingInput("seeds"))));
+
+        //rejected before computing f(k)
+        assert!(matches!(builder().index(usize::MAX).build(), Err(FibError::InvalidIndex{ k: usize::MAX, max_k: 16 })));
+        assert!(matches!(builder().index(3).max_k(0).build(), Err(FibError::NotEnoughRows{ max_k: 0 })));
+    }
+
+    #[test]
+    fn test_default_max_k(){
+        //f(64) for 1, 1 still fits a u64, and the default layout fits 2^8 rows
+        let circ = FibCircuit::builder().seeds(Fp::from(1), Fp::from(1)).index(DEFAULT_MAX_K).build().unwrap();
+        assert_eq!(circ.instances()[2], Fp::from(17_167_680_177_565));
+
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()));
+    }
+}
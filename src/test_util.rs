@@ -0,0 +1,34 @@
+use std::{fs, path::{Path, PathBuf}};
+
+/*
+@note
+•   Helpers shared by the unit tests and the fib binary's tests, which can't see the
+    library's #[cfg(test)] items. Not part of the API.
+*/
+
+//a fresh directory under the system temp dir, removed when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir{
+    pub fn new(name: &str) -> Self{
+        let dir = std::env::temp_dir().join(format!("fib-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    pub fn dir(&self) -> &Path{
+        &self.0
+    }
+
+    //a file in the directory, as the string a command line would take
+    pub fn path(&self, name: &str) -> String{
+        self.0.join(name).display().to_string()
+    }
+}
+
+impl Drop for TempDir{
+    fn drop(&mut self){
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,189 @@
+use blake2b_simd::Params as Blake2bParams;
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::{keygen_vk, VerifyingKey},
+    poly::commitment::Params,
+};
+
+use crate::circuits::circuit_naive::{FibCircuit, Visibility};
+use crate::error::FibError;
+use crate::proof::{Curve, Layout, Reader};
+
+pub type Fingerprint = [u8; 32];
+
+/*
+@note
+•   Blake2b-256 of the pinned verifying key: the constraint system, the domain, the
+    fixed commitments and the permutation, i.e. everything keygen_vk derives from the
+    circuit shape. It is what halo2 itself hashes into the transcript, so two keys with
+    the same fingerprint accept the same proofs.
+*/
+pub fn fingerprint(vk: &VerifyingKey<EqAffine>) -> Fingerprint{
+    let pinned = format!("{:?}", vk.pinned());
+    let hash = Blake2bParams::new()
+        .hash_length(32)
+        .personal(b"FibCircuit-VK-FP")
+        .to_state()
+        .update(&(pinned.len() as u64).to_le_bytes())
+        .update(pinned.as_bytes())
+        .finalize();
+    let mut fingerprint = [0; 32];
+    fingerprint.copy_from_slice(hash.as_bytes());
+    fingerprint
+}
+
+pub fn check_fingerprint(vk: &VerifyingKey<EqAffine>, expected: Fingerprint) -> Result<(), FibError>{
+    let found = fingerprint(vk);
+    if found != expected {
+        return Err(FibError::KeyMismatch{ expected, found });
+    }
+    Ok(())
+}
+
+//the domain of vk has 2^k rows
+pub fn domain_k(vk: &VerifyingKey<EqAffine>) -> u32{
+    vk.get_domain().empty_lagrange().len().trailing_zeros()
+}
+
+/*
+@note
+•   halo2_proofs 0.3 can't write a VerifyingKey out, so an exported key is the
+    FibCircuit configuration it was generated for plus its fingerprint.
+    Importing runs keygen_vk again on that configuration and only hands the key
+    back if it matches the fingerprint.
+
+    magic           4 bytes     "FIBK"
+    version         u16         VK_VERSION
+    curve           u8          Curve id
+    layout          u8          Layout id (only Naive can be imported)
+    visibility      u8          Visibility id
+    max_k           u64
+    domain k        u32
+    fingerprint     32 bytes
+*/
+pub const VK_MAGIC: [u8; 4] = *b"FIBK";
+pub const VK_VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ExportedVk{
+    pub curve: Curve,
+    pub layout: Layout,
+    pub visibility: Visibility,
+    pub max_k: u64,
+    pub domain_k: u32,
+    pub fingerprint: Fingerprint,
+}
+
+//describe a key generated for FibCircuit with the given max_k and visibility
+pub fn export_vk(vk: &VerifyingKey<EqAffine>, max_k: usize, visibility: Visibility) -> ExportedVk{
+    ExportedVk{
+        curve: Curve::PastaFp,
+        layout: Layout::Naive,
+        visibility,
+        max_k: max_k as u64,
+        domain_k: domain_k(vk),
+        fingerprint: fingerprint(vk),
+    }
+}
+
+//rebuild the key against the FibCircuit configuration and check it is the exported one
+pub fn import_vk(params: &Params<EqAffine>, exported: &ExportedVk) -> Result<VerifyingKey<EqAffine>, FibError>{
+    if exported.layout != Layout::Naive {
+        return Err(FibError::Serialization(format!("can't rebuild a key for the {:?} layout", exported.layout)));
+    }
+    let max_k = usize::try_from(exported.max_k)
+        .map_err(|_| FibError::Serialization(format!("max_k {} does not fit in usize", exported.max_k)))?;
+    let circuit = FibCircuit::<Fp>{
+        max_k,
+        visibility: exported.visibility,
+        ..FibCircuit::default()
+    };
+    let vk = keygen_vk(params, &circuit)?;
+    check_fingerprint(&vk, exported.fingerprint)?;
+    Ok(vk)
+}
+
+impl ExportedVk{
+    pub fn to_bytes(&self) -> Vec<u8>{
+        let mut bytes = vec![];
+        bytes.extend(VK_MAGIC);
+        bytes.extend(VK_VERSION.to_le_bytes());
+        bytes.push(self.curve.id());
+        bytes.push(self.layout.id());
+        bytes.push(self.visibility.id());
+        bytes.extend(self.max_k.to_le_bytes());
+        bytes.extend(self.domain_k.to_le_bytes());
+        bytes.extend(self.fingerprint);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, FibError>{
+        let mut reader = Reader{ bytes };
+        if reader.take(4)? != VK_MAGIC {
+            return Err(FibError::Serialization("not a key file (bad magic)".into()));
+        }
+        let version = u16::from_le_bytes(reader.array()?);
+        if version != VK_VERSION {
+            return Err(FibError::Serialization(format!("unsupported version {}", version)));
+        }
+        let exported = ExportedVk{
+            curve: Curve::from_id(reader.array::<1>()?[0])?,
+            layout: Layout::from_id(reader.array::<1>()?[0])?,
+            visibility: Visibility::from_id(reader.array::<1>()?[0])?,
+            max_k: u64::from_le_bytes(reader.array()?),
+            domain_k: u32::from_le_bytes(reader.array()?),
+            fingerprint: reader.array()?,
+        };
+        if !reader.bytes.is_empty() {
+            return Err(FibError::Serialization(format!("{} trailing bytes", reader.bytes.len())));
+        }
+        Ok(exported)
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+
+    fn vk(params: &Params<EqAffine>, max_k: usize, visibility: Visibility) -> VerifyingKey<EqAffine>{
+        let circuit = FibCircuit::<Fp>{
+            max_k,
+            visibility,
+            ..FibCircuit::default()
+        };
+        keygen_vk(params, &circuit).unwrap()
+    }
+
+    #[test]
+    fn test_export_import(){
+        let params: Params<EqAffine> = Params::new(8);
+        let original = vk(&params, 16, Visibility::PublicSeeds);
+        let exported = export_vk(&original, 16, Visibility::PublicSeeds);
+        assert_eq!(exported.domain_k, 8);
+
+        let decoded = ExportedVk::from_bytes(&exported.to_bytes()).unwrap();
+        assert_eq!(decoded, exported);
+        let imported = import_vk(&params, &decoded).unwrap();
+        assert_eq!(fingerprint(&imported), fingerprint(&original));
+    }
+
+    #[test]
+    fn test_mismatch(){
+        let params: Params<EqAffine> = Params::new(8);
+        let exported = export_vk(&vk(&params, 16, Visibility::PublicZ), 16, Visibility::PublicZ);
+
+        //a different max_k or visibility rebuilds a different key
+        for (max_k, visibility) in [(15, Visibility::PublicZ), (16, Visibility::PublicK)] {
+            let forged = ExportedVk{
+                max_k,
+                visibility,
+                ..exported
+            };
+            assert!(matches!(import_vk(&params, &forged), Err(FibError::KeyMismatch{ .. })));
+        }
+
+        //so does a different domain
+        let params_9: Params<EqAffine> = Params::new(9);
+        assert!(matches!(import_vk(&params_9, &exported), Err(FibError::KeyMismatch{ .. })));
+    }
+}
@@ -0,0 +1,458 @@
+use halo2_proofs::{arithmetic::Field, pasta::group::ff::PrimeField};
+use std::{cmp::Ordering, fmt, ops::Add};
+
+/*
+@note
+•   Native witness generation. The circuits don't compute anything while they assign
+    cells: the values come from a Trace computed here, synthesize only decides where
+    they go.
+•   A Trace<F, N> holds the terms of a recurrence of order N from f(-N+1) up to f(k+1)
+    (or f(N) if k is smaller, so the seeds are always there). The terms before f(0)
+    are the window the linear recurrence chip starts from, the seed rows run the
+    recurrence backwards to get it. f(k+1) is for the rotation layout, whose last
+    row holds the pair f(k), f(k+1).
+•   The circuits freeze the sequence at the hidden k: the row for f(n) holds f(n) if
+    n <= k and f(k) after that, which is what output(n) and inputs(n) return.
+•   ExactTrace is the same sequence over the integers. Past some index the terms no
+    longer fit the field and the circuit works with their residues mod p instead,
+    wrapped::<F>() says where that starts.
+•   The doubling layout holds no terms of the claim: its rows are F(n), F(n+1) of the
+    standard sequence for the prefixes n of the bits of k, which DoublingTrace computes.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Trace<F: Field, const N: usize>{
+    //f(-N+1)..f(max(k, N-1) + 1), f(n) is terms[n + N - 1]
+    terms: Vec<F>,
+    k: usize,
+}
+
+pub type FibTrace<F> = Trace<F, 2>;
+
+impl<F: Field, const N: usize> Trace<F, N>{
+    /*
+    @note
+    •   f(m-1) = (f(m+N-1) - Σ_i<N c_i·f(m+N-1-i)) / c_N for the terms before f(0).
+        With c_N = 0 they don't exist and are left at 0, the chip rejects such a
+        recurrence anyway.
+    •   The trace holds about k terms, so the circuits check k <= max_k (their check())
+        before building one.
+    */
+    pub fn new(coefficients: [F; N], seeds: [F; N], k: usize) -> Self{
+        let c_n: F = Option::from(coefficients[N - 1].invert()).unwrap_or(F::ZERO);
+        let mut window = seeds;
+        let mut prior = Vec::with_capacity(N - 1);
+        for _ in 0..N - 1 {
+            let sum = window[..N - 1].iter().rev().zip(coefficients).fold(F::ZERO, |acc, (x, c)| acc + c*x);
+            let f = (window[N - 1] - sum) * c_n;
+            window = std::array::from_fn(|i| if i == 0 { f } else { window[i - 1] });
+            prior.push(f);
+        }
+        let terms = prior.into_iter().rev().chain(sequence(coefficients, seeds).take(k.max(N - 1) + 2)).collect();
+        Self{ terms, k }
+    }
+
+    pub fn k(&self) -> usize{
+        self.k
+    }
+
+    //f(n) for -N < n <= max(k, N-1) + 1
+    pub fn term(&self, n: isize) -> F{
+        self.terms[(n + N as isize - 1) as usize]
+    }
+
+    //f(0)..f(N-1)
+    pub fn seeds(&self) -> [F; N]{
+        std::array::from_fn(|i| self.term(i as isize))
+    }
+
+    //f(0)..f(k)
+    pub fn terms(&self) -> &[F]{
+        &self.terms[N - 1..N + self.k]
+    }
+
+    pub fn z(&self) -> F{
+        self.term(self.k as isize)
+    }
+
+    //what the row for f(n) outputs, the sequence frozen at f(k)
+    pub fn output(&self, n: usize) -> F{
+        self.term(n.min(self.k) as isize)
+    }
+
+    //the inputs of the row for f(n), n >= 1: the window f(-N+1)..f(0) or the outputs of the rows before
+    pub fn inputs(&self, n: usize) -> [F; N]{
+        std::array::from_fn(|i| match (n + i).checked_sub(N) {
+            Some(m) if m > 0 => self.output(m),
+            _ => self.term(n as isize + i as isize - N as isize),
+        })
+    }
+
+    //the active bit of the row for f(n)
+    pub fn active(&self, n: usize) -> F{
+        if n <= self.k { F::ONE } else { F::ZERO }
+    }
+}
+
+impl<F: Field> Trace<F, 2>{
+    //f(0) = x, f(1) = y
+    pub fn fib(x: F, y: F, k: usize) -> Self{
+        Self::new([F::ONE, F::ONE], [x, y], k)
+    }
+}
+
+//f(k) for f(0) = x, f(1) = y, computed natively
+pub fn fib<F: Field>(x: F, y: F, k: usize) -> F{
+    (0..k).fold((x, y), |(a, b), _| (b, a + b)).0
+}
+
+/*
+@note
+•   The terms f(0), f(1), ... of the recurrence computed natively, over any field.
+*/
+pub fn sequence<F: Field, const N: usize>(coefficients: [F; N], seeds: [F; N]) -> impl Iterator<Item = F>{
+    std::iter::successors(Some(seeds), move |window| {
+        let next = window.iter().rev().zip(coefficients).fold(F::ZERO, |acc, (x, c)| acc + c*x);
+        Some(std::array::from_fn(|i| if i + 1 < N { window[i + 1] } else { next }))
+    })
+    .map(|window| window[0])
+}
+
+/*
+@note
+•   The rows of the doubling layout, see circuit_doubling. Row i holds F(n), F(n+1)
+    and n for the first i bits n of k (most significant first), the same prefix s of
+    the slack max_k - k, and the bits of both after them; the last row has no bits,
+    n = k and s = max_k - k. Both are written with the given number of bits, the
+    layout has to reject a k past max_k or a max_k that doesn't fit them.
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DoublingTrace<F: Field>{
+    //F(n), F(n+1), n, s for every prefix, from 0 to k
+    pairs: Vec<[F; 4]>,
+    //the bits of k and of the slack, most significant first
+    bits: Vec<[bool; 2]>,
+}
+
+impl<F: Field> DoublingTrace<F>{
+    pub fn new(k: usize, max_k: usize, bits: usize) -> Self{
+        let slack = max_k.saturating_sub(k);
+        let bits: Vec<[bool; 2]> = (0..bits).rev().map(|i| [k, slack].map(|n| (n >> i) & 1 == 1)).collect();
+        let mut pairs = vec![[F::ZERO, F::ONE, F::ZERO, F::ZERO]];
+        for [bit, slack_bit] in &bits {
+            let [u, v, n, s] = pairs[pairs.len() - 1];
+            //F(2n), F(2n+1)
+            let (even, odd) = (u*(v.double() - u), u.square() + v.square());
+            let s = if *slack_bit { s.double() + F::ONE } else { s.double() };
+            pairs.push(match bit {
+                false => [even, odd, n.double(), s],
+                true => [odd, even + odd, n.double() + F::ONE, s],
+            });
+        }
+        Self{ pairs, bits }
+    }
+
+    //number of doubling rows, the output row comes after them
+    pub fn bits(&self) -> usize{
+        self.bits.len()
+    }
+
+    //F(n), F(n+1), n, s of the row, 0..=bits()
+    pub fn row(&self, row: usize) -> [F; 4]{
+        self.pairs[row]
+    }
+
+    //the bits of k and of the slack the doubling row consumes
+    pub fn bit(&self, row: usize) -> [F; 2]{
+        self.bits[row].map(|bit| if bit { F::ONE } else { F::ZERO })
+    }
+}
+
+/*
+@note
+•   Just enough of an unsigned big integer for ExactTrace: addition, multiplication by
+    a u64, comparison, printing and reduction into a field. Little endian 64 bit limbs
+    without trailing zeros, so 0 has none and equal numbers have equal limbs.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Natural{
+    limbs: Vec<u64>,
+}
+
+impl Natural{
+    fn normalized(mut self) -> Self{
+        while self.limbs.last() == Some(&0) {
+            self.limbs.pop();
+        }
+        self
+    }
+
+    //big endian hex digits with an optional 0x, the format of PrimeField::MODULUS
+    pub fn from_hex(hex: &str) -> Option<Self>{
+        let digits = hex.strip_prefix("0x").unwrap_or(hex).as_bytes();
+        let mut limbs = vec![];
+        for chunk in digits.rchunks(16) {
+            limbs.push(u64::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?);
+        }
+        Some(Self{ limbs }.normalized())
+    }
+
+    //the order of F
+    pub fn modulus<F: PrimeField>() -> Self{
+        Self::from_hex(F::MODULUS).unwrap_or_default()
+    }
+
+    pub fn bits(&self) -> u64{
+        match self.limbs.last() {
+            Some(top) => 64*self.limbs.len() as u64 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+
+    pub fn mul_u64(&self, c: u64) -> Self{
+        let mut carry = 0u128;
+        let mut limbs = Vec::with_capacity(self.limbs.len() + 1);
+        for limb in &self.limbs {
+            let product = *limb as u128 * c as u128 + carry;
+            limbs.push(product as u64);
+            carry = product >> 64;
+        }
+        limbs.push(carry as u64);
+        Self{ limbs }.normalized()
+    }
+
+    //the residue mod the order of F
+    pub fn to_field<F: PrimeField>(&self) -> F{
+        let radix = F::from(u64::MAX) + F::ONE;
+        self.limbs.iter().rev().fold(F::ZERO, |acc, limb| acc*radix + F::from(*limb))
+    }
+}
+
+impl From<u64> for Natural{
+    fn from(n: u64) -> Self{
+        Self{ limbs: vec![n] }.normalized()
+    }
+}
+
+impl Add<&Natural> for &Natural{
+    type Output = Natural;
+
+    fn add(self, other: &Natural) -> Natural{
+        let (long, short) = if self.limbs.len() >= other.limbs.len() { (self, other) } else { (other, self) };
+        let mut carry = false;
+        let mut limbs = Vec::with_capacity(long.limbs.len() + 1);
+        for (i, limb) in long.limbs.iter().enumerate() {
+            let (sum, c1) = limb.overflowing_add(short.limbs.get(i).copied().unwrap_or(0));
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            limbs.push(sum);
+            carry = c1 || c2;
+        }
+        limbs.push(carry as u64);
+        Natural{ limbs }.normalized()
+    }
+}
+
+impl Ord for Natural{
+    fn cmp(&self, other: &Self) -> Ordering{
+        self.limbs.len().cmp(&other.limbs.len()).then_with(|| self.limbs.iter().rev().cmp(other.limbs.iter().rev()))
+    }
+}
+
+impl PartialOrd for Natural{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering>{
+        Some(self.cmp(other))
+    }
+}
+
+//decimal
+impl fmt::Display for Natural{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result{
+        const BASE: u128 = 10_000_000_000_000_000_000;
+        //base 10^19 digits, least significant first
+        let mut digits = vec![];
+        let mut rest = self.limbs.clone();
+        while !rest.is_empty() {
+            let mut remainder = 0u128;
+            for limb in rest.iter_mut().rev() {
+                let current = (remainder << 64) | *limb as u128;
+                *limb = (current / BASE) as u64;
+                remainder = current % BASE;
+            }
+            digits.push(remainder as u64);
+            while rest.last() == Some(&0) {
+                rest.pop();
+            }
+        }
+        match digits.split_last() {
+            Some((top, rest)) => {
+                write!(f, "{}", top)?;
+                rest.iter().rev().try_for_each(|digit| write!(f, "{:019}", digit))
+            }
+            None => write!(f, "0"),
+        }
+    }
+}
+
+/*
+@note
+•   f(0)..f(k) over the integers, for a recurrence with non-negative coefficients and
+    seeds (Fibonacci, or any of the sequences in linear_recurrence).
+*/
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExactTrace{
+    terms: Vec<Natural>,
+}
+
+impl ExactTrace{
+    pub fn new<const N: usize>(coefficients: [u64; N], seeds: [u64; N], k: usize) -> Self{
+        let mut terms: Vec<Natural> = seeds.map(Natural::from).to_vec();
+        while terms.len() <= k {
+            let n = terms.len();
+            let next = (1..=N).fold(Natural::default(), |acc, i| &acc + &terms[n - i].mul_u64(coefficients[i - 1]));
+            terms.push(next);
+        }
+        terms.truncate(k + 1);
+        Self{ terms }
+    }
+
+    pub fn fib(x: u64, y: u64, k: usize) -> Self{
+        Self::new([1, 1], [x, y], k)
+    }
+
+    pub fn k(&self) -> usize{
+        self.terms.len() - 1
+    }
+
+    //f(0)..f(k)
+    pub fn terms(&self) -> &[Natural]{
+        &self.terms
+    }
+
+    pub fn z(&self) -> &Natural{
+        &self.terms[self.terms.len() - 1]
+    }
+
+    //the first n with f(n) >= p, from there on the field only holds f(n) mod p
+    pub fn wrapped<F: PrimeField>(&self) -> Option<usize>{
+        let modulus = Natural::modulus::<F>();
+        self.terms.iter().position(|term| *term >= modulus)
+    }
+
+    //the terms reduced mod p, what a Trace over F holds for the same seeds
+    pub fn to_field<F: PrimeField>(&self) -> Vec<F>{
+        self.terms.iter().map(Natural::to_field).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use halo2_proofs::pasta::{Fp, Fq};
+
+    #[test]
+    fn test_trace(){
+        //1, 1, 2, 3, 5, 8 with f(-1) = 0, frozen at f(5) = 8
+        let trace = FibTrace::fib(Fp::from(1), Fp::from(1), 5);
+        assert_eq!(trace.terms(), [1, 1, 2, 3, 5, 8].map(Fp::from));
+        assert_eq!(trace.term(-1), Fp::ZERO);
+        assert_eq!(trace.z(), Fp::from(8));
+        assert_eq!(trace.inputs(1), [Fp::ZERO, Fp::ONE]);
+        assert_eq!(trace.inputs(6), [Fp::from(5), Fp::from(8)]);
+        assert_eq!(trace.inputs(8), [Fp::from(8), Fp::from(8)]);
+        assert_eq!(trace.output(9), Fp::from(8));
+        assert_eq!((trace.active(5), trace.active(6)), (Fp::ONE, Fp::ZERO));
+
+        //k = 0 still has both seeds, every row outputs f(0)
+        let trace = FibTrace::fib(Fp::from(2), Fp::from(5), 0);
+        assert_eq!(trace.seeds(), [Fp::from(2), Fp::from(5)]);
+        assert_eq!(trace.terms(), [Fp::from(2)]);
+        assert_eq!(trace.inputs(2), [Fp::from(2), Fp::from(2)]);
+    }
+
+    //the table from circuit_doubling, k = 5 = 0b101 and max_k = 7 in 3 bits
+    #[test]
+    fn test_doubling_trace(){
+        let trace: DoublingTrace<Fp> = DoublingTrace::new(5, 7, 3);
+        assert_eq!(trace.bits(), 3);
+        assert_eq!((0..3).map(|row| trace.bit(row)).collect::<Vec<_>>(), [[1, 0], [0, 1], [1, 0]].map(|bits| bits.map(Fp::from)));
+        assert_eq!(
+            (0..=3).map(|row| trace.row(row)).collect::<Vec<_>>(),
+            [[0, 1, 0, 0], [1, 1, 1, 0], [1, 2, 2, 1], [5, 8, 5, 2]].map(|row| row.map(Fp::from)),
+        );
+
+        //the last row is F(k), F(k+1) of the standard sequence
+        let k = 1000;
+        let trace: DoublingTrace<Fq> = DoublingTrace::new(k, 1023, 10);
+        assert_eq!(trace.row(10), [fib(Fq::ZERO, Fq::ONE, k), fib(Fq::ZERO, Fq::ONE, k + 1), Fq::from(k as u64), Fq::from(23)]);
+    }
+
+    //the terms before f(0) continue the recurrence backwards
+    #[test]
+    fn test_window(){
+        //tribonacci from 0, 0, 1: f(-1) = 1 - 0 - 0, f(-2) = 0 - 1 - 0
+        let trace: Trace<Fq, 3> = Trace::new([Fq::ONE; 3], [Fq::ZERO, Fq::ZERO, Fq::ONE], 6);
+        assert_eq!((trace.term(-2), trace.term(-1)), (-Fq::ONE, Fq::ONE));
+        assert_eq!(trace.inputs(1), [-Fq::ONE, Fq::ONE, Fq::ZERO]);
+        assert_eq!(trace.inputs(3), [Fq::ZERO, Fq::ZERO, Fq::ONE]);
+        assert_eq!(trace.inputs(4), [Fq::ZERO, Fq::ONE, Fq::ONE]);
+        assert_eq!(trace.z(), Fq::from(7));
+
+        //pell (c = 2, 1) from 0, 1: f(-1) = 1 - 2*0
+        let trace: Trace<Fp, 2> = Trace::new([Fp::from(2), Fp::ONE], [Fp::ZERO, Fp::ONE], 5);
+        assert_eq!(trace.term(-1), Fp::ONE);
+        assert_eq!(trace.terms(), [0, 1, 2, 5, 12, 29].map(Fp::from));
+    }
+
+    #[test]
+    fn test_natural(){
+        let f100 = ExactTrace::fib(0, 1, 100);
+        assert_eq!(f100.z().to_string(), "354224848179261915075");
+        assert_eq!(f100.terms()[94].to_string(), "19740274219868223167");
+        assert_eq!(Natural::default().to_string(), "0");
+        assert_eq!(Natural::from(10_000_000_000_000_000_000).to_string(), "10000000000000000000");
+        assert_eq!(Natural::from(u64::MAX).mul_u64(u64::MAX).to_string(), "340282366920938463426481119284349108225");
+
+        assert_eq!(Natural::from_hex("0x10"), Some(Natural::from(16)));
+        assert_eq!(Natural::from_hex("0x0000"), Some(Natural::default()));
+        assert_eq!(Natural::from_hex("0xg"), None);
+        assert_eq!(Natural::modulus::<Fp>().bits(), 255);
+        assert!(Natural::modulus::<Fq>() > Natural::modulus::<Fp>());
+        assert_eq!(Natural::modulus::<Fp>().to_field::<Fp>(), Fp::ZERO);
+    }
+
+    /*
+    @note
+    •   Up to the wrap the field terms are the integers, past it they are their residues:
+        the exact trace reduced mod p is the field trace all the way, and it can't tell
+        where the values stopped being the integers.
+    */
+    #[test]
+    fn test_wrapped(){
+        let exact = ExactTrace::fib(3, 7, 500);
+        let trace = FibTrace::fib(Fp::from(3), Fp::from(7), 500);
+        assert_eq!(exact.to_field::<Fp>(), trace.terms());
+
+        let n = exact.wrapped::<Fp>().unwrap();
+        let modulus = Natural::modulus::<Fp>();
+        assert!(exact.terms()[n - 1] < modulus && exact.terms()[n] >= modulus);
+        assert_ne!(exact.terms()[n].to_field::<Fp>(), Fp::ZERO);
+        assert!(exact.terms()[n].bits() >= 255);
+
+        //Fq is a bit larger, it wraps at the same index or later
+        assert!(exact.wrapped::<Fq>().unwrap() >= n);
+        assert_eq!(ExactTrace::fib(3, 7, n - 1).wrapped::<Fp>(), None);
+        assert_eq!(ExactTrace::fib(0, 1, 0).z(), &Natural::default());
+    }
+
+    //the exact sequences agree with the field ones while they fit
+    #[test]
+    fn test_exact_sequences(){
+        let tribonacci = ExactTrace::new([1, 1, 1], [0, 0, 1], 40);
+        let trace: Trace<Fp, 3> = Trace::new([Fp::ONE; 3], [Fp::ZERO, Fp::ZERO, Fp::ONE], 40);
+        assert_eq!(tribonacci.to_field::<Fp>(), trace.terms());
+        assert_eq!(tribonacci.terms()[8].to_string(), "24");
+
+        let pell = ExactTrace::new([2, 1], [0, 1], 5);
+        assert_eq!(pell.terms().iter().map(|t| t.to_string()).collect::<Vec<_>>(), ["0", "1", "2", "5", "12", "29"]);
+        assert_eq!(sequence([Fq::from(2), Fq::ONE], [Fq::ZERO, Fq::ONE]).nth(5), Some(Fq::from(29)));
+    }
+}
@@ -0,0 +1,71 @@
+use fib_circuit::{
+    circuits::{circuit_naive::{FibCircuit, Visibility}, poseidon::PoseidonField},
+    witness::fib,
+};
+use halo2_proofs::{
+    circuit::Value,
+    dev::MockProver,
+    pasta::{Fp, Fq},
+};
+use rand::{rngs::SmallRng, RngCore, SeedableRng};
+use rand_core::OsRng;
+
+const MAX_K: usize = 16;
+const SAMPLES: usize = 12;
+
+const MODES: [Visibility; 4] = [
+    Visibility::PublicK,
+    Visibility::PublicZ,
+    Visibility::PublicSeeds,
+    Visibility::AllPrivateCommitted,
+];
+
+/*
+@note
+•   Random seeds anywhere in the field, a random k in 2..=max_k and a random visibility:
+    the z computed natively is accepted, z + 1 is rejected. Failures print the sample
+    and the seed; FIB_SEED=<seed> replays the same samples.
+*/
+fn check_random_claims<F: PoseidonField + Ord>() {
+    let seed = match std::env::var("FIB_SEED") {
+        Ok(seed) => seed.parse().expect("FIB_SEED has to be a u64"),
+        Err(_) => OsRng.next_u64(),
+    };
+    let mut rng = SmallRng::seed_from_u64(seed);
+    for _ in 0..SAMPLES {
+        let (x, y, r) = (F::random(&mut rng), F::random(&mut rng), F::random(&mut rng));
+        let k = 2 + (rng.next_u64() % (MAX_K as u64 - 1)) as usize;
+        let visibility = MODES[(rng.next_u64() % MODES.len() as u64) as usize];
+        let sample = format!("x = {:?}, y = {:?}, k = {}, r = {:?}, {:?} (FIB_SEED={})", x, y, k, r, visibility, seed);
+
+        let z = fib(x, y, k);
+        let circ = FibCircuit::builder()
+            .seeds(x, y)
+            .index(k)
+            .output(z)
+            .blinding(r)
+            .max_k(MAX_K)
+            .visibility(visibility)
+            .build()
+            .unwrap();
+        let prover = MockProver::run(8, &circ, vec![circ.instances()]).unwrap();
+        assert_eq!(prover.verify(), Ok(()), "{}", sample);
+
+        let wrong = FibCircuit {
+            z: Value::known(z + F::ONE),
+            ..circ
+        };
+        let prover = MockProver::run(8, &wrong, vec![wrong.instances()]).unwrap();
+        assert!(prover.verify().is_err(), "z + 1 accepted for {}", sample);
+    }
+}
+
+#[test]
+fn test_random_claims_fp() {
+    check_random_claims::<Fp>();
+}
+
+#[test]
+fn test_random_claims_fq() {
+    check_random_claims::<Fq>();
+}
@@ -0,0 +1,199 @@
+use fib_circuit::{
+    circuits::{
+        circuit_multi::{FibClaim, MultiFibCircuit},
+        circuit_naive::FibCircuit,
+    },
+    error::FibError,
+    proof::{Curve, Layout, Proof},
+    prover::{keygen, prove, verify, verify_batch},
+    vk::fingerprint,
+};
+use halo2_proofs::{
+    pasta::{EqAffine, Fp},
+    plonk::Circuit,
+    poly::commitment::Params,
+};
+
+fn fib_circuit(k: usize, z: u64) -> FibCircuit<Fp> {
+    FibCircuit::builder()
+        .seeds(Fp::from(1), Fp::from(1))
+        .index(k)
+        .output(Fp::from(z))
+        .blinding(Fp::from(7))
+        .max_k(16)
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn test_round_trip() {
+    let params: Params<EqAffine> = Params::new(8);
+    let circuit = fib_circuit(10, 89);
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let instances = circuit.instances();
+    let proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+    assert!(verify(&params, pk.get_vk(), &proof, &[&instances]).is_ok());
+}
+
+#[test]
+fn test_one_key_for_every_k() {
+    let params: Params<EqAffine> = Params::new(8);
+    let pk = keygen(&params, &fib_circuit(0, 1).without_witnesses()).unwrap();
+
+    for (k, z) in [(2, 2), (7, 21), (16, 1597)] {
+        let circuit = fib_circuit(k, z);
+        let instances = circuit.instances();
+        let proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+        assert!(verify(&params, pk.get_vk(), &proof, &[&instances]).is_ok());
+    }
+}
+
+#[test]
+fn test_tampered_proof() {
+    let params: Params<EqAffine> = Params::new(8);
+    let circuit = fib_circuit(10, 89);
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let instances = circuit.instances();
+    let mut proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+    let mid = proof.len() / 2;
+    proof[mid] ^= 1;
+    assert!(matches!(
+        verify(&params, pk.get_vk(), &proof, &[&instances]),
+        Err(FibError::InvalidProof)
+    ));
+}
+
+#[test]
+fn test_wrong_public_output() {
+    let params: Params<EqAffine> = Params::new(8);
+    let circuit = fib_circuit(10, 89);
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let mut instances = circuit.instances();
+    let proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+    instances[2] = Fp::from(144);
+    assert!(matches!(
+        verify(&params, pk.get_vk(), &proof, &[&instances]),
+        Err(FibError::InvalidProof)
+    ));
+}
+
+#[test]
+fn test_wrong_instance_shape() {
+    let params: Params<EqAffine> = Params::new(8);
+    let circuit = fib_circuit(10, 89);
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let instances = circuit.instances();
+    let proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+    assert!(matches!(
+        verify(&params, pk.get_vk(), &proof, &[]),
+        Err(FibError::InvalidInstances)
+    ));
+    assert!(matches!(
+        verify(&params, pk.get_vk(), &proof, &[&instances, &instances]),
+        Err(FibError::InvalidInstances)
+    ));
+    let too_long = vec![Fp::zero(); 1 << 8];
+    assert!(matches!(
+        verify(&params, pk.get_vk(), &proof, &[&too_long]),
+        Err(FibError::InvalidInstances)
+    ));
+}
+
+#[test]
+fn test_envelope_round_trip() {
+    let domain_k = 8;
+    let params: Params<EqAffine> = Params::new(domain_k);
+    let circuit = fib_circuit(10, 89);
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let instances = circuit.instances();
+    let envelope = Proof {
+        curve: Curve::PastaFp,
+        layout: Layout::Naive,
+        max_k: circuit.max_k as u64,
+        domain_k,
+        vk_fingerprint: Some(fingerprint(pk.get_vk())),
+        instances: vec![instances.clone()],
+        proof: prove(&params, &pk, circuit, &[&instances]).unwrap(),
+    };
+
+    let decoded = Proof::from_bytes(&envelope.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded, envelope);
+    decoded.check_vk(pk.get_vk()).unwrap();
+    assert!(verify(&params, pk.get_vk(), &decoded.proof, &decoded.instance_slices()).is_ok());
+}
+
+#[test]
+fn test_envelope_wrong_key() {
+    let params: Params<EqAffine> = Params::new(8);
+    let pk = keygen(&params, &fib_circuit(0, 1).without_witnesses()).unwrap();
+    let other = FibCircuit::<Fp> {
+        max_k: 12,
+        ..FibCircuit::default()
+    };
+    let other_pk = keygen(&params, &other).unwrap();
+
+    let envelope = Proof {
+        curve: Curve::PastaFp,
+        layout: Layout::Naive,
+        max_k: 16,
+        domain_k: 8,
+        vk_fingerprint: Some(fingerprint(pk.get_vk())),
+        instances: vec![],
+        proof: vec![],
+    };
+    assert!(envelope.check_vk(pk.get_vk()).is_ok());
+    assert!(matches!(
+        envelope.check_vk(other_pk.get_vk()),
+        Err(FibError::KeyMismatch { .. })
+    ));
+}
+
+#[test]
+fn test_verify_batch() {
+    let params: Params<EqAffine> = Params::new(8);
+    let pk = keygen(&params, &fib_circuit(0, 1).without_witnesses()).unwrap();
+
+    let mut proofs = vec![];
+    let mut instances = vec![];
+    for (k, z) in [(3, 3), (9, 55), (12, 233), (16, 1597)] {
+        let circuit = fib_circuit(k, z);
+        instances.push(circuit.instances());
+        proofs.push(prove(&params, &pk, circuit, &[instances.last().unwrap()]).unwrap());
+    }
+    let columns: Vec<[&[Fp]; 1]> = instances.iter().map(|column| [column.as_slice()]).collect();
+    let batch = |proofs: &[Vec<u8>]| {
+        let items: Vec<(&[u8], &[&[Fp]])> = proofs
+            .iter()
+            .zip(&columns)
+            .map(|(proof, columns)| (proof.as_slice(), columns.as_slice()))
+            .collect();
+        verify_batch(&params, pk.get_vk(), &items)
+    };
+    assert!(batch(&proofs).is_ok());
+
+    let mid = proofs[2].len() / 2;
+    proofs[2][mid] ^= 1;
+    assert!(matches!(batch(&proofs), Err(FibError::InvalidBatchItem { index: 2 })));
+}
+
+#[test]
+fn test_multi_round_trip() {
+    let params: Params<EqAffine> = Params::new(10);
+    let circuit = MultiFibCircuit {
+        claims: [(10, 89), (3, 3), (16, 1597)]
+            .map(|(k, z)| FibClaim::from(fib_circuit(k, z)))
+            .to_vec(),
+        max_k: 16,
+        ..MultiFibCircuit::default()
+    };
+    let pk = keygen(&params, &circuit.without_witnesses()).unwrap();
+
+    let instances = circuit.instances();
+    let proof = prove(&params, &pk, circuit, &[&instances]).unwrap();
+    assert!(verify(&params, pk.get_vk(), &proof, &[&instances]).is_ok());
+}